@@ -0,0 +1,274 @@
+//! Validates dot configuration against dot directory and system
+
+use crate::config::{Config, Context, File};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    // entry path the issue belongs to, none for global issues
+    pub entry: Option<PathBuf>,
+    pub message: String,
+}
+
+impl Issue {
+    fn new<S: Into<String>>(severity: Severity, entry: Option<&Path>, message: S) -> Issue {
+        Issue {
+            severity,
+            entry: entry.map(Path::to_owned),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.entry {
+            Some(e) => write!(f, "{}: {}: {}", self.severity, e.display(), self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+// List all paths under root, relative to root
+fn walk(root: &Path, rel: &Path, out: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(root.join(rel)) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = rel.join(entry.file_name());
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        out.push(path.clone());
+        if is_dir {
+            walk(root, &path, out);
+        }
+    }
+}
+
+fn check_glob(ctx: &Context, config: &Config, file: &File, issues: &mut Vec<Issue>) {
+    let entry = Some(file.path.as_path());
+    if let Err(e) = glob::Pattern::new(&file.path.display().to_string()) {
+        issues.push(Issue::new(
+            Severity::Error,
+            entry,
+            format!("invalid glob: {}", e),
+        ));
+        return;
+    }
+    if let Err(e) = ctx.get_path(&file.location) {
+        issues.push(Issue::new(Severity::Error, entry, e.to_string()));
+    }
+    // sources are in place in bare mode
+    let base = if ctx.bare {
+        ctx.get_path(&file.location).unwrap_or_default()
+    } else {
+        ctx.dot_path(&file.location, "")
+    };
+    match config
+        .settings(file)
+        .and_then(|settings| file.expand(&base, &settings))
+    {
+        Ok(m) if m.is_empty() => issues.push(Issue::new(
+            Severity::Warning,
+            entry,
+            "glob does not match anything in dot directory",
+        )),
+        Ok(_) => {}
+        Err(e) => issues.push(Issue::new(Severity::Error, entry, e.to_string())),
+    }
+}
+
+fn check_file(ctx: &Context, config: &Config, file: &File, issues: &mut Vec<Issue>) {
+    if file.is_glob() {
+        return check_glob(ctx, config, file, issues);
+    }
+    let entry = Some(file.path.as_path());
+    let src = ctx.dot_path(&file.location, &file.path);
+    if !ctx.bare && !src.exists() {
+        issues.push(Issue::new(
+            Severity::Error,
+            entry,
+            format!("source {} does not exist", src.display()),
+        ));
+    }
+    let dst = match ctx.get_path(&file.location) {
+        Ok(base) => Some(base.join(&file.path)),
+        Err(e) => {
+            issues.push(Issue::new(Severity::Error, entry, e.to_string()));
+            None
+        }
+    };
+    if let Some(dst) = &dst {
+        if ctx.bare {
+            if let Err(e) = ctx.home_path(dst) {
+                issues.push(Issue::new(Severity::Error, entry, e.to_string()));
+            }
+        }
+        if !dst.exists() {
+            issues.push(Issue::new(
+                Severity::Warning,
+                entry,
+                format!("{} is not installed", dst.display()),
+            ));
+        }
+    }
+    for include in &file.include {
+        let pattern = include.strip_prefix('!').unwrap_or(include);
+        if let Err(e) = glob::Pattern::new(pattern) {
+            issues.push(Issue::new(
+                Severity::Error,
+                entry,
+                format!("invalid include {}: {}", include, e),
+            ));
+        }
+    }
+    if file.exclude.is_empty() {
+        return;
+    }
+    let mut paths = Vec::new();
+    walk(&src, Path::new(""), &mut paths);
+    if let Some(dst) = &dst {
+        walk(dst, Path::new(""), &mut paths);
+    }
+    for exclude in &file.exclude {
+        match glob::Pattern::new(exclude) {
+            Ok(pattern) => {
+                if !paths.iter().any(|p| pattern.matches_path(p)) {
+                    issues.push(Issue::new(
+                        Severity::Warning,
+                        entry,
+                        format!("exclude {} does not match anything", exclude),
+                    ));
+                }
+            }
+            Err(e) => issues.push(Issue::new(
+                Severity::Error,
+                entry,
+                format!("invalid exclude {}: {}", exclude, e),
+            )),
+        }
+    }
+}
+
+fn check_git(ctx: &Context, config: &Config, issues: &mut Vec<Issue>) {
+    let git = match config.git() {
+        Some(g) => g,
+        None => return,
+    };
+    let git_dir = match ctx.get_path(&git.location) {
+        Ok(base) => base.join(&git.path),
+        Err(e) => {
+            issues.push(Issue::new(Severity::Error, None, format!("git: {}", e)));
+            return;
+        }
+    };
+    if !git_dir.is_dir() {
+        issues.push(Issue::new(
+            Severity::Error,
+            None,
+            format!("git dir {} does not exist", git_dir.display()),
+        ));
+        return;
+    }
+    let valid = process::Command::new("git")
+        .arg("--git-dir")
+        .arg(&git_dir)
+        .arg("rev-parse")
+        .arg("--git-dir")
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false);
+    if !valid {
+        issues.push(Issue::new(
+            Severity::Error,
+            None,
+            format!("{} is not a git directory", git_dir.display()),
+        ));
+    }
+}
+
+// Returns all issues found in config
+pub fn check(ctx: &Context, config: &Config) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    if !ctx.dot.is_dir() {
+        issues.push(Issue::new(
+            Severity::Error,
+            None,
+            format!("dot directory {} does not exist", ctx.dot.display()),
+        ));
+    }
+    for (i, file) in config.files.iter().enumerate() {
+        for other in &config.files[i + 1..] {
+            let entry = Some(file.path.as_path());
+            if other.location == file.location && other.path == file.path {
+                issues.push(Issue::new(Severity::Error, entry, "entry is defined twice"));
+                continue;
+            }
+            // home, config and absolute entries share the top of dot directory
+            let (a, b) = (
+                file.location.storage(&file.path),
+                other.location.storage(&other.path),
+            );
+            if other.location != file.location
+                && !file.is_glob()
+                && !other.is_glob()
+                && (a.starts_with(&b) || b.starts_with(&a))
+            {
+                issues.push(Issue::new(
+                    Severity::Error,
+                    entry,
+                    format!(
+                        "entry is stored at the same place as {}",
+                        other.path.display()
+                    ),
+                ));
+                continue;
+            }
+            // compare system paths, unavailable locations are reported by check_file
+            let (a, b) = match (ctx.get_path(&file.location), ctx.get_path(&other.location)) {
+                (Ok(a), Ok(b)) => (a.join(file.glob_base()), b.join(other.glob_base())),
+                _ => continue,
+            };
+            if (!file.is_glob() && b.starts_with(&a)) || (!other.is_glob() && a.starts_with(&b)) {
+                issues.push(Issue::new(
+                    Severity::Error,
+                    entry,
+                    format!("entry overlaps with {}", other.path.display()),
+                ));
+            }
+        }
+        check_file(ctx, config, file, &mut issues);
+    }
+    for exclude in &config.exclude {
+        if let Err(e) = glob::Pattern::new(exclude) {
+            issues.push(Issue::new(
+                Severity::Error,
+                None,
+                format!("invalid global exclude {}: {}", exclude, e),
+            ));
+        }
+    }
+    check_git(ctx, config, &mut issues);
+    issues
+}
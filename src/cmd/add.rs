@@ -1,4 +1,4 @@
-use super::Command;
+use super::{bare, Command};
 use crate::config::{Config, Context};
 use crate::error::Result;
 use std::path::PathBuf;
@@ -18,6 +18,10 @@ impl Command for AddCmd {
             println!("==> adding {}", file.display());
             config.add_file(ctx, file, self.recursive)?;
         }
+        // files are committed in place, they are tracked right away
+        if ctx.bare {
+            bare::add(ctx, config, &self.files)?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,382 @@
+use super::{bare, Command};
+use crate::config::{Config, Context};
+use crate::error::Result;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+// Well known dotfiles, relative to home. Only ssh config is listed, never keys.
+const HOME_CATALOG: &[&str] = &[
+    ".bash_aliases",
+    ".bash_logout",
+    ".bash_profile",
+    ".bashrc",
+    ".profile",
+    ".inputrc",
+    ".zshenv",
+    ".zprofile",
+    ".zshrc",
+    ".zlogin",
+    ".p10k.zsh",
+    ".vimrc",
+    ".gvimrc",
+    ".vim",
+    ".ideavimrc",
+    ".emacs",
+    ".emacs.d",
+    ".spacemacs",
+    ".editorconfig",
+    ".gitconfig",
+    ".gitignore_global",
+    ".gitmessage",
+    ".tmux.conf",
+    ".screenrc",
+    ".wezterm.lua",
+    ".alacritty.toml",
+    ".alacritty.yml",
+    ".Xresources",
+    ".xinitrc",
+    ".xprofile",
+    ".ssh/config",
+    ".curlrc",
+    ".wgetrc",
+    ".psqlrc",
+];
+
+// Well known app config directories and files, relative to XDG config directory
+const CONFIG_CATALOG: &[&str] = &[
+    "bash",
+    "zsh",
+    "fish",
+    "nushell",
+    "starship.toml",
+    "nvim",
+    "vim",
+    "helix",
+    "emacs",
+    "doom",
+    "Code/User",
+    "git",
+    "tmux",
+    "zellij",
+    "alacritty",
+    "kitty",
+    "wezterm",
+    "foot",
+    "ghostty",
+    "i3",
+    "sway",
+    "hypr",
+    "waybar",
+    "polybar",
+    "rofi",
+    "dunst",
+    "picom",
+    "htop",
+    "btop",
+    "ranger",
+    "lf",
+    "yazi",
+    "mpv",
+    "zathura",
+    "direnv",
+];
+
+// Entries bigger than this are not selected by default, caches aside
+const MAX_DEFAULT_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, StructOpt)]
+pub struct AdoptCmd {
+    /// Add default selection without asking
+    #[structopt(short = "y", long = "yes")]
+    yes: bool,
+    /// Only list suggested files
+    #[structopt(short = "n", long = "dry-run")]
+    dry_run: bool,
+}
+
+struct Candidate {
+    path: PathBuf,
+    dir: bool,
+    // size without caches
+    size: u64,
+    caches: Vec<(PathBuf, u64)>,
+}
+
+impl Candidate {
+    fn is_default(&self) -> bool {
+        self.size <= MAX_DEFAULT_SIZE
+    }
+}
+
+// Directory names of caches and logs, compared case insensitively
+const CACHE_NAMES: &[&str] = &[
+    ".cache",
+    "cache",
+    "caches",
+    "cachestorage",
+    "code cache",
+    "gpucache",
+    "shadercache",
+    "__pycache__",
+    "node_modules",
+    "log",
+    "logs",
+    "tmp",
+];
+
+fn is_cache(name: &str) -> bool {
+    CACHE_NAMES.contains(&name.to_lowercase().as_str())
+}
+
+// Total size of path, symbolic links are not followed. Cache directories are
+// not descended into, they are collected with their size instead. What cannot
+// be read is reported and left out.
+fn size(path: &Path, caches: &mut Vec<(PathBuf, u64)>) -> u64 {
+    let skip = |e: std::io::Error| {
+        // warnings go to stderr, like when planning
+        eprintln!("!=> cannot read {}: {}, skipping it", path.display(), e);
+        0
+    };
+    let meta = match fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(e) => return skip(e),
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    let entries = match fs::read_dir(path) {
+        Ok(e) => e,
+        Err(e) => return skip(e),
+    };
+    let mut total = 0;
+    for entry in entries {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                skip(e);
+                continue;
+            }
+        };
+        let sub = entry.path();
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        if is_dir && is_cache(&entry.file_name().to_string_lossy()) {
+            let cache = size(&sub, &mut Vec::new());
+            caches.push((sub, cache));
+            continue;
+        }
+        total += size(&sub, caches);
+    }
+    total
+}
+
+fn human(size: u64) -> String {
+    let mut size = size as f64;
+    for unit in &["B", "K", "M", "G"] {
+        if size < 1024.0 {
+            return match *unit {
+                "B" => format!("{}{}", size, unit),
+                _ => format!("{:.1}{}", size, unit),
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1}T", size)
+}
+
+// Catalog entries present on system and not managed yet
+fn scan(ctx: &Context, config: &Config) -> Result<Vec<Candidate>> {
+    let home = HOME_CATALOG.iter().map(|p| ctx.home.join(p));
+    let xdg = CONFIG_CATALOG.iter().map(|p| ctx.xdg_config.join(p));
+    let mut candidates = Vec::new();
+    for path in home.chain(xdg) {
+        if fs::symlink_metadata(&path).is_err() {
+            continue;
+        }
+        if config.find_entry(ctx, &path)?.is_some() {
+            continue;
+        }
+        // e.g nvim/lua is managed, nvim is left alone
+        let mut managed = config
+            .files
+            .iter()
+            .filter_map(|f| ctx.get_path(&f.location).ok().map(|p| p.join(&f.path)));
+        if managed.any(|p| p.starts_with(&path)) {
+            continue;
+        }
+        let mut caches = Vec::new();
+        let size = size(&path, &mut caches);
+        // a linked directory is synced as a directory
+        let dir = fs::metadata(&path).map(|m| m.is_dir()).unwrap_or(false);
+        candidates.push(Candidate {
+            path,
+            dir,
+            size,
+            caches,
+        });
+    }
+    Ok(candidates)
+}
+
+// Parse selection like `1 3-5`, numbers start at 1
+fn parse_selection(answer: &str, count: usize) -> Option<Vec<usize>> {
+    let mut selected = Vec::new();
+    for part in answer.split(|c: char| c == ',' || c.is_whitespace()) {
+        if part.is_empty() {
+            continue;
+        }
+        let (start, end) = match part.split_once('-') {
+            Some((s, e)) => (s.parse::<usize>().ok()?, e.parse::<usize>().ok()?),
+            None => {
+                let n = part.parse::<usize>().ok()?;
+                (n, n)
+            }
+        };
+        if start == 0 || end > count || start > end {
+            return None;
+        }
+        selected.extend((start..=end).map(|n| n - 1));
+    }
+    selected.sort_unstable();
+    selected.dedup();
+    Some(selected)
+}
+
+// Ask which candidates to add until answer is valid, empty answer keeps defaults
+fn select(candidates: &[Candidate], defaults: Vec<usize>) -> Result<Vec<usize>> {
+    let mut shown = defaults
+        .iter()
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if shown.is_empty() {
+        shown = "none".to_owned();
+    }
+    loop {
+        print!("==> entries to add, e.g 1 3-5, all or none [{}]: ", shown);
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(defaults);
+        }
+        match answer.trim() {
+            "" => return Ok(defaults),
+            "all" => return Ok((0..candidates.len()).collect()),
+            "none" => return Ok(Vec::new()),
+            answer => match parse_selection(answer, candidates.len()) {
+                Some(selected) => return Ok(selected),
+                None => println!("!=> invalid selection {}", answer),
+            },
+        }
+    }
+}
+
+impl Command for AdoptCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        let candidates = scan(ctx, config)?;
+        if candidates.is_empty() {
+            println!("==> nothing to adopt");
+            return Ok(());
+        }
+        println!("==> found {} path(s) to adopt:", candidates.len());
+        for (i, candidate) in candidates.iter().enumerate() {
+            let large = if candidate.is_default() {
+                ""
+            } else {
+                ", large"
+            };
+            println!(
+                "  {}) {} {}{}",
+                i + 1,
+                candidate.path.display(),
+                human(candidate.size),
+                large
+            );
+            for (cache, size) in &candidate.caches {
+                println!("    - cache {} {}, excluded", cache.display(), human(*size));
+            }
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+        let defaults = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_default())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let selected = if self.yes {
+            defaults
+        } else {
+            select(&candidates, defaults)?
+        };
+        let mut added = Vec::new();
+        for candidate in selected.into_iter().map(|i| &candidates[i]) {
+            println!("==> adding {}", candidate.path.display());
+            config.add_file(ctx, &candidate.path, candidate.dir)?;
+            for (cache, _) in &candidate.caches {
+                config.add_exclude(ctx, &cache.display().to_string())?;
+            }
+            added.push(candidate.path.to_owned());
+        }
+        // files are committed in place, they are tracked right away
+        if ctx.bare && !added.is_empty() {
+            bare::add(ctx, config, &added)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::TempDir;
+
+    #[test]
+    fn selection() {
+        assert_eq!(parse_selection("", 3), Some(vec![]));
+        assert_eq!(parse_selection("2", 3), Some(vec![1]));
+        assert_eq!(parse_selection("3 1,2", 3), Some(vec![0, 1, 2]));
+        assert_eq!(parse_selection("2-4 3", 5), Some(vec![1, 2, 3]));
+        assert_eq!(parse_selection("1-1", 1), Some(vec![0]));
+    }
+
+    #[test]
+    fn selection_rejected() {
+        assert_eq!(parse_selection("0", 3), None);
+        assert_eq!(parse_selection("0-2", 3), None);
+        assert_eq!(parse_selection("3-1", 3), None);
+        assert_eq!(parse_selection("4", 3), None);
+        assert_eq!(parse_selection("2-4", 3), None);
+        assert_eq!(parse_selection("a", 3), None);
+        assert_eq!(parse_selection("1-", 3), None);
+    }
+
+    #[test]
+    fn cache_names() {
+        assert!(is_cache("cache"));
+        assert!(is_cache("Cache"));
+        assert!(is_cache("GPUCache"));
+        assert!(is_cache("node_modules"));
+        assert!(is_cache("logs"));
+        assert!(!is_cache("cache_utils"));
+        assert!(!is_cache("mycache"));
+        assert!(!is_cache("catalog"));
+    }
+
+    #[test]
+    fn caches_left_out_of_size() -> Result<()> {
+        let tmp = TempDir::new("adopt")?;
+        let root = tmp.path().join("nvim");
+        fs::create_dir_all(root.join("lua/cache_utils"))?;
+        fs::create_dir_all(root.join("cache"))?;
+        fs::write(root.join("init.lua"), "12")?;
+        fs::write(root.join("lua/cache_utils/a.lua"), "123")?;
+        fs::write(root.join("cache/blob"), "12345")?;
+        let mut caches = Vec::new();
+        assert_eq!(size(&root, &mut caches), 5);
+        assert_eq!(caches, vec![(root.join("cache"), 5)]);
+        Ok(())
+    }
+}
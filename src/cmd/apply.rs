@@ -0,0 +1,50 @@
+use super::{apply_install, apply_update, plan, sync_command, Direction, SyncOpts};
+use crate::config::{Config, Context, SyncMode};
+use crate::error::DotError;
+use crate::plan::Plan;
+use crate::report::Outcome;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct ApplyCmd {
+    #[structopt(flatten)]
+    opts: SyncOpts,
+    /// Plan saved with install --plan-out or update --plan-out
+    #[structopt(name = "plan", parse(from_os_str))]
+    plan: PathBuf,
+}
+
+impl ApplyCmd {
+    pub fn run(&self, ctx: &Context, config: &Config) -> Outcome {
+        let saved = if ctx.bare {
+            Err(DotError::BareMode("apply".to_owned()).into())
+        } else {
+            Plan::read(&self.plan)
+        };
+        let direction = saved
+            .as_ref()
+            .map(|p| p.direction)
+            .unwrap_or(Direction::Install);
+        // saved plan keeps skipped changes, compare it with everything that would be mirrored
+        let sync_ctx = saved.and_then(|p| {
+            p.verify(
+                ctx,
+                &plan(ctx, config, p.direction, Some(SyncMode::Mirror))?,
+            )
+        });
+        let verbose = self.opts.is_text();
+        sync_command(
+            ctx,
+            config,
+            direction,
+            &self.opts,
+            None,
+            sync_ctx,
+            |sync_ctx| match direction {
+                Direction::Install => apply_install(ctx, config, sync_ctx, verbose),
+                Direction::Update => apply_update(ctx, config, sync_ctx, verbose),
+            },
+        )
+    }
+}
@@ -0,0 +1,15 @@
+use crate::error::Result;
+use crate::privilege;
+use structopt::StructOpt;
+
+// Internal helper, reads a list of operations from stdin and applies them.
+// It never reads dot config, only validated operations are accepted.
+#[derive(Debug, StructOpt)]
+pub struct ApplyPrivilegedCmd {}
+
+impl ApplyPrivilegedCmd {
+    pub fn run(&self) -> Result<()> {
+        let ops = privilege::read_operations()?;
+        privilege::apply(&ops)
+    }
+}
@@ -0,0 +1,256 @@
+use super::update::message;
+use super::SyncOpts;
+use crate::config::{Config, Context, File, Git, Granularity};
+use crate::error::{DotError, Result};
+use crate::git::{self, Remote};
+use crate::report::Outcome;
+use crate::sync::{self, Diff};
+use std::path::{Path, PathBuf};
+
+fn git_dir<'a>(ctx: &Context, config: &'a Config) -> Result<(&'a Git, PathBuf)> {
+    match (config.git(), config.git_dir(ctx)) {
+        (Some(git), Some(git_dir)) => Ok((git, git_dir)),
+        _ => Err(DotError::NoGitDir.into()),
+    }
+}
+
+// Files selected by entry, relative to home. Sync settings apply like in copy
+// mode, tracked files that are gone are kept so their deletion is committed.
+// Tracked files no longer selected are left alone, untracking them is up to the user.
+fn entry_files(
+    ctx: &Context,
+    config: &Config,
+    git_dir: &Path,
+    file: &File,
+) -> Result<Vec<PathBuf>> {
+    let base = ctx.get_path(&file.location)?;
+    let roots = if file.is_glob() {
+        file.expand(&base, &config.settings(file)?)?
+    } else {
+        vec![file.path.to_owned()]
+    };
+    let mut files = Vec::new();
+    for root in roots {
+        let src = base.join(&root);
+        if !src.exists() {
+            continue;
+        }
+        // excludes of glob matches are relative to glob base
+        let prefix = if file.is_glob() {
+            root.strip_prefix(file.glob_base()).unwrap_or(&root)
+        } else {
+            Path::new("")
+        };
+        let settings = config.settings(file)?.prefix(prefix);
+        for path in sync::select(&src, &settings)? {
+            files.push(ctx.home_path(&path)?);
+        }
+    }
+    let entry = ctx.home_path(&base.join(&file.path))?;
+    for path in git::tracked(ctx, git_dir, &[entry])? {
+        if files.contains(&path) {
+            continue;
+        }
+        if ctx.home.join(&path).exists() {
+            // warnings go to stderr like in copy mode
+            eprintln!(
+                "!=> {} is excluded but tracked, untrack it with `dotto git rm --cached {}`",
+                path.display(),
+                path.display()
+            );
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+// Managed entries as (path, files) relative to home, dot config included when
+// it is in home
+fn managed(ctx: &Context, config: &Config, git_dir: &Path) -> Result<Vec<(PathBuf, Vec<PathBuf>)>> {
+    let mut entries = Vec::new();
+    for file in &config.files {
+        let path = ctx.home_path(&ctx.get_path(&file.location)?.join(&file.path))?;
+        entries.push((path, entry_files(ctx, config, git_dir, file)?));
+    }
+    if let Ok(path) = ctx.home_path(&ctx.dot_config) {
+        entries.push((path.to_owned(), vec![path]));
+    }
+    Ok(entries)
+}
+
+fn managed_files(ctx: &Context, config: &Config, git_dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = managed(ctx, config, git_dir)?;
+    Ok(entries.into_iter().flat_map(|(_, files)| files).collect())
+}
+
+// Git status restricted to files, nothing to compare without files
+fn changes(ctx: &Context, git_dir: &Path, files: &[PathBuf], untracked: bool) -> Result<Vec<Diff>> {
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    git::changes(ctx, git_dir, files, untracked)
+}
+
+// Git status restricted to managed files
+pub fn status(ctx: &Context, config: &Config, untracked: bool) -> Result<()> {
+    let (_, git_dir) = git_dir(ctx, config)?;
+    let files = managed_files(ctx, config, &git_dir)?;
+    let diffs = changes(ctx, &git_dir, &files, untracked)?;
+    if diffs.is_empty() {
+        println!("==> everything is up to date");
+        return Ok(());
+    }
+    println!("==> home differs from dot repository:");
+    for diff in &diffs {
+        println!("  - {}", diff);
+    }
+    Ok(())
+}
+
+// Track paths in place, they must be in home. Only files selected by entries
+// settings are tracked.
+pub fn add(ctx: &Context, config: &Config, paths: &[PathBuf]) -> Result<()> {
+    let (_, git_dir) = git_dir(ctx, config)?;
+    let mut rel = Vec::new();
+    for path in paths {
+        let (_, path) = config.repo_path(ctx, path)?;
+        rel.push(path);
+    }
+    // config is tracked along, it is staged again on update
+    if let Ok(path) = ctx.home_path(&ctx.dot_config) {
+        rel.push(path);
+    }
+    let files = managed_files(ctx, config, &git_dir)?
+        .into_iter()
+        .filter(|f| rel.iter().any(|r| f.starts_with(r)))
+        .collect::<Vec<_>>();
+    // an empty path list would add the whole home
+    if files.is_empty() {
+        return Ok(());
+    }
+    git::add(ctx, git_dir, &files)
+}
+
+// Reject options that need a sync plan
+fn check_opts(opts: &SyncOpts, plan_out: bool) -> Result<()> {
+    if !opts.is_text() {
+        return Err(DotError::BareMode("--format json".to_owned()).into());
+    }
+    if plan_out {
+        return Err(DotError::BareMode("--plan-out".to_owned()).into());
+    }
+    Ok(())
+}
+
+// Commit changes of managed files, new files selected by entries included
+fn update(ctx: &Context, config: &Config, opts: &SyncOpts, plan_out: bool) -> Result<Outcome> {
+    check_opts(opts, plan_out)?;
+    let (git, git_dir) = git_dir(ctx, config)?;
+    let entries = managed(ctx, config, &git_dir)?;
+    let files = entries
+        .iter()
+        .flat_map(|(_, files)| files.iter().cloned())
+        .collect::<Vec<_>>();
+    let changes = changes(ctx, &git_dir, &files, true)?;
+    if changes.is_empty() {
+        println!("==> everything is up to date");
+        return Ok(Outcome::NothingToDo);
+    }
+    println!("==> these changes will be committed:");
+    for diff in &changes {
+        println!("  - {}", diff);
+    }
+    if opts.dry_run {
+        return Ok(Outcome::Planned);
+    }
+    if !opts.confirm()? {
+        return Ok(Outcome::Cancelled);
+    }
+    // push could only fail once changes are committed, refuse early
+    let remote = if git.push && git.commit != Granularity::None {
+        let remote = Remote::new(ctx, &git_dir, git)?;
+        remote.check_push(ctx, &git_dir)?;
+        Some(remote)
+    } else {
+        None
+    };
+    // only changed files are staged, other changes in home are left alone
+    let changed = changes
+        .iter()
+        .map(|d| d.path().to_owned())
+        .collect::<Vec<_>>();
+    match git.commit {
+        Granularity::None => git::add(ctx, &git_dir, &changed)?,
+        Granularity::Run => {
+            let paths = entries
+                .iter()
+                .map(|(p, _)| p.to_owned())
+                .collect::<Vec<_>>();
+            let message = message(git, &paths, &changes);
+            git::commit_paths(ctx, &git_dir, &message, &changed)?
+        }
+        Granularity::Entry => {
+            for (path, files) in &entries {
+                let group = changes
+                    .iter()
+                    .filter(|d| files.iter().any(|f| f == d.path()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if group.is_empty() {
+                    continue;
+                }
+                let paths = group
+                    .iter()
+                    .map(|d| d.path().to_owned())
+                    .collect::<Vec<_>>();
+                let message = message(git, std::slice::from_ref(path), &group);
+                git::commit_paths(ctx, &git_dir, &message, &paths)?;
+            }
+        }
+    }
+    if let Some(remote) = remote {
+        println!("==> pushing to {}", remote);
+        if let Err(e) = remote.push(ctx, &git_dir) {
+            eprintln!("!=> changes committed, push failed: {}", e);
+        }
+    }
+    Ok(Outcome::Applied)
+}
+
+// Files are already in place, install only pulls when auto pull is enabled
+fn install(ctx: &Context, config: &Config, opts: &SyncOpts, plan_out: bool) -> Result<Outcome> {
+    check_opts(opts, plan_out)?;
+    let (git, git_dir) = git_dir(ctx, config)?;
+    if !git.pull {
+        println!("==> bare mode: files are already in place");
+        return Ok(Outcome::NothingToDo);
+    }
+    let remote = Remote::new(ctx, &git_dir, git)?;
+    if opts.dry_run {
+        println!("==> would pull from {}", remote);
+        return Ok(Outcome::Planned);
+    }
+    println!("==> pulling from {}", remote);
+    if remote.pull(ctx, &git_dir)? {
+        Ok(Outcome::Applied)
+    } else {
+        println!("==> already up to date");
+        Ok(Outcome::NothingToDo)
+    }
+}
+
+fn outcome(res: Result<Outcome>) -> Outcome {
+    res.unwrap_or_else(|e| {
+        println!("!=> {}", e);
+        Outcome::Failed
+    })
+}
+
+pub fn update_command(ctx: &Context, config: &Config, opts: &SyncOpts, plan_out: bool) -> Outcome {
+    outcome(update(ctx, config, opts, plan_out))
+}
+
+pub fn install_command(ctx: &Context, config: &Config, opts: &SyncOpts, plan_out: bool) -> Outcome {
+    outcome(install(ctx, config, opts, plan_out))
+}
@@ -0,0 +1,37 @@
+use crate::check::{self, Severity};
+use crate::config::{Config, Context};
+use crate::error::Result;
+use crate::report::Outcome;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct CheckCmd {
+    /// Only report errors
+    #[structopt(short = "q", long = "quiet")]
+    quiet: bool,
+}
+
+impl CheckCmd {
+    // Errors are reported through exit code, config is still saved
+    pub fn run(&self, ctx: &Context, config: &mut Config) -> Result<Outcome> {
+        let issues = check::check(ctx, config);
+        let errors = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count();
+        for issue in &issues {
+            if self.quiet && issue.severity != Severity::Error {
+                continue;
+            }
+            println!("!=> {}", issue);
+        }
+        if errors > 0 {
+            println!("!=> config check failed with {} error(s)", errors);
+            return Ok(Outcome::Failed);
+        }
+        if issues.is_empty() {
+            println!("==> config is valid");
+        }
+        Ok(Outcome::Applied)
+    }
+}
@@ -0,0 +1,148 @@
+use super::Command;
+use crate::config::{Config, Context, Granularity, SyncMode};
+use crate::error::Result;
+use std::path::Path;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum ConfigCmd {
+    /// Show merged configuration
+    #[structopt(name = "show")]
+    Show {
+        /// Show which layer each setting comes from
+        #[structopt(long = "origin")]
+        origin: bool,
+    },
+}
+
+fn origin(show: bool, path: Option<&Path>) -> String {
+    match path {
+        Some(p) if show => format!("  # {}", p.display()),
+        _ => String::new(),
+    }
+}
+
+impl Command for ConfigCmd {
+    fn run(&self, _ctx: &Context, config: &mut Config) -> Result<()> {
+        let ConfigCmd::Show { origin: show } = self;
+        let show = *show;
+        if show {
+            println!("# layers:");
+            for layer in config.layers() {
+                println!("#   - {}", layer.path.display());
+            }
+        }
+        println!("files:");
+        for file in &config.files {
+            println!(
+                "  - path: {}{}",
+                file.path.display(),
+                origin(show, config.file_origin(file))
+            );
+            println!("    location: {}", file.location);
+            if file.recursive {
+                println!("    recursive: true");
+            }
+            if file.symbolic {
+                println!("    symbolic: true");
+            }
+            if file.mode != SyncMode::Mirror {
+                println!("    mode: {}", file.mode);
+            }
+            if !file.exclude.is_empty() {
+                println!("    exclude:");
+                for exclude in &file.exclude {
+                    println!(
+                        "      - {}{}",
+                        exclude,
+                        origin(show, config.exclude_origin(file, exclude))
+                    );
+                }
+            }
+            if !file.include.is_empty() {
+                println!("    include:");
+                for include in &file.include {
+                    println!("      - {}", include);
+                }
+            }
+        }
+        if !config.exclude.is_empty() {
+            println!("exclude:");
+            for exclude in &config.exclude {
+                println!(
+                    "  - {}{}",
+                    exclude,
+                    origin(show, config.global_exclude_origin(exclude))
+                );
+            }
+        }
+        if let Some(git) = config.git() {
+            println!("git:{}", origin(show, config.setting_origin("git")));
+            println!("  path: {}", git.path.display());
+            println!("  location: {}", git.location);
+            if let Some(subject) = &git.subject {
+                println!("  subject: {}", subject);
+            }
+            if let Some(body) = &git.body {
+                // body is often multi-line
+                println!("  body: {:?}", body);
+            }
+            if git.commit != Granularity::Run {
+                println!("  commit: {}", git.commit);
+            }
+            if let Some(remote) = &git.remote {
+                println!("  remote: {}", remote);
+            }
+            if let Some(branch) = &git.branch {
+                println!("  branch: {}", branch);
+            }
+            if git.push {
+                println!("  push: true");
+            }
+            if git.pull {
+                println!("  pull: true");
+            }
+        }
+        if let Some(dot) = &config.dot {
+            println!(
+                "dot: {}{}",
+                dot.display(),
+                origin(show, config.setting_origin("dot"))
+            );
+        }
+        if let Some(escalate) = &config.escalate {
+            println!(
+                "escalate: {}{}",
+                escalate,
+                origin(show, config.setting_origin("escalate"))
+            );
+        }
+        if let Some(max) = config.max_deletions {
+            println!(
+                "max_deletions: {}{}",
+                max,
+                origin(show, config.setting_origin("max_deletions"))
+            );
+        }
+        if let Some(trash) = config.trash {
+            println!(
+                "trash: {}{}",
+                trash,
+                origin(show, config.setting_origin("trash"))
+            );
+        }
+        if !config.locations.is_empty() {
+            println!("locations:");
+            for (name, base) in &config.locations {
+                let key = format!("locations.{}", name);
+                println!(
+                    "  {}: {}{}",
+                    name,
+                    base,
+                    origin(show, config.setting_origin(&key))
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -5,15 +5,62 @@ use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 pub struct ExcludeCmd {
+    /// Apply patterns to every entry
+    #[structopt(short = "g", long = "global")]
+    global: bool,
+    /// List global and per entry excludes
+    #[structopt(short = "l", long = "list")]
+    list: bool,
+    /// Remove patterns instead of adding them
+    #[structopt(long = "remove")]
+    remove: bool,
     #[structopt(name = "excludes")]
     excludes: Vec<String>,
 }
 
+impl ExcludeCmd {
+    fn list(&self, config: &Config) {
+        if !config.exclude.is_empty() {
+            println!("==> global:");
+            for exclude in &config.exclude {
+                println!("  - {}", exclude);
+            }
+        }
+        for file in config.files.iter().filter(|f| !f.exclude.is_empty()) {
+            println!("==> {}:", file.path.display());
+            for exclude in &file.exclude {
+                println!("  - {}", exclude);
+            }
+        }
+    }
+}
+
 impl Command for ExcludeCmd {
     fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        if self.list {
+            self.list(config);
+            return Ok(());
+        }
         for exclude in &self.excludes {
-            println!("==> adding exclusion {}", exclude);
-            if let Err(e) = config.add_exclude(ctx, &exclude) {
+            let res = match (self.global, self.remove) {
+                (true, false) => {
+                    println!("==> adding global exclusion {}", exclude);
+                    config.add_global_exclude(exclude)
+                }
+                (true, true) => {
+                    println!("==> removing global exclusion {}", exclude);
+                    config.remove_global_exclude(exclude)
+                }
+                (false, false) => {
+                    println!("==> adding exclusion {}", exclude);
+                    config.add_exclude(ctx, exclude)
+                }
+                (false, true) => {
+                    println!("==> removing exclusion {}", exclude);
+                    config.remove_exclude(ctx, exclude)
+                }
+            };
+            if let Err(e) = res {
                 println!("!=> {}", e);
             }
         }
@@ -0,0 +1,154 @@
+use super::{plan, roots, Command, Direction};
+use crate::config::{self, Config, Context};
+use crate::error::{DotError, Result};
+use crate::import::{Exported, Manifest, MANIFEST};
+use crate::sync::{self, TempDir};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+// Package created in stow directory
+const PACKAGE: &str = "dotto";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Tar,
+    Stow,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<ExportFormat, String> {
+        match s {
+            "tar" => Ok(ExportFormat::Tar),
+            "stow" => Ok(ExportFormat::Stow),
+            _ => Err(format!("unknown format {}, expected tar or stow", s)),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportFormat::Tar => write!(f, "tar"),
+            ExportFormat::Stow => write!(f, "stow"),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ExportCmd {
+    /// Export format: tar or stow
+    #[structopt(long = "format", default_value = "tar")]
+    format: ExportFormat,
+    /// Archive to create, or stow directory to create a `dotto` package in
+    #[structopt(name = "dest", parse(from_os_str))]
+    dest: PathBuf,
+}
+
+// Install entries into a staging root, as they would be installed on system.
+// Returns home under staging root, only files in home are portable, and the
+// manifest of exported entries.
+fn stage(ctx: &Context, config: &Config, staging: &Path) -> Result<(PathBuf, Manifest)> {
+    let mut staged = ctx.clone();
+    staged.set_root(staging)?;
+    let mut guard = sync::Guard::default();
+    let mut outside = Vec::new();
+    for sctx in plan(&staged, config, Direction::Install, None)? {
+        let system = ctx.get_path(&sctx.location)?.join(&sctx.path);
+        if ctx.home_path(&system).is_err() {
+            outside.push(system);
+            continue;
+        }
+        let (src, dst) = roots(&staged, &sctx.location, &sctx.path, Direction::Install)?;
+        sync::sync(src, dst, &sctx.diffs, &mut guard)?;
+        println!("  - {}", system.display());
+    }
+    if !outside.is_empty() {
+        println!("!=> cannot export:");
+        for path in outside {
+            println!("  - {}: outside home", path.display());
+        }
+    }
+    fs::create_dir_all(&staged.home)?;
+    let mut manifest = Manifest {
+        files: Vec::new(),
+        exclude: config.exclude.clone(),
+    };
+    for file in &config.files {
+        let system = ctx.get_path(&file.location)?.join(file.glob_base());
+        if let Ok(archive) = ctx.home_path(&system) {
+            if staged.home.join(&archive).symlink_metadata().is_ok() {
+                let entry = file.clone();
+                manifest.files.push(Exported { archive, entry });
+            }
+        }
+    }
+    Ok((staged.home, manifest))
+}
+
+impl ExportCmd {
+    fn export(&self, ctx: &Context, config: &Config, tmp: &Path, out: &Path) -> Result<()> {
+        println!("==> exporting to {} {}:", self.format, out.display());
+        let (home, manifest) = stage(ctx, config, tmp)?;
+        match self.format {
+            ExportFormat::Tar => {
+                // entries are rebuilt from manifest by `dotto import-archive`
+                let path = home.join(MANIFEST);
+                fs::create_dir_all(path.parent().unwrap_or(&home))?;
+                let yaml = serde_yaml::to_string(&manifest)
+                    .map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+                fs::write(&path, yaml)?;
+                let status = process::Command::new("tar")
+                    .arg("-c")
+                    .arg("-f")
+                    .arg(out)
+                    .arg("-C")
+                    .arg(&home)
+                    .arg(".")
+                    .status()?;
+                if !status.success() {
+                    return Err(DotError::TarFailed("create".to_owned()).into());
+                }
+                println!(
+                    "==> extract it with `tar -xf {} -C ~ --exclude ./{}`",
+                    out.display(),
+                    MANIFEST
+                );
+            }
+            ExportFormat::Stow => {
+                sync::move_path(&home, out)?;
+                let dir = out.parent().unwrap_or(out);
+                println!(
+                    "==> install it with `stow -d {} -t ~ {}`",
+                    dir.display(),
+                    PACKAGE
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Command for ExportCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        // installed state is the home work tree itself, see `dotto git archive`
+        if ctx.bare {
+            return Err(DotError::BareMode("export".to_owned()).into());
+        }
+        let dest = config::absolute(&self.dest)?;
+        let out = match self.format {
+            ExportFormat::Tar => dest,
+            ExportFormat::Stow => dest.join(PACKAGE),
+        };
+        if out.exists() {
+            return Err(DotError::AlreadyExists(out.display().to_string()).into());
+        }
+        let tmp = TempDir::new("export")?;
+        self.export(ctx, config, tmp.path(), &out)
+    }
+}
@@ -1,7 +1,6 @@
 use super::Command;
 use crate::config::{Config, Context};
 use crate::error::Result;
-use std::process;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -19,23 +18,16 @@ impl Command for GitCmd {
         if let Some(v) = git.values_of("") {
             svalues = v.collect::<Vec<&str>>();
         }
-        match git.subcommand() {
-            (cmd, Some(sub_m)) => {
-                let mut evalues = Vec::new();
-                if let Some(v) = sub_m.values_of("") {
-                    evalues = v.collect::<Vec<&str>>();
-                }
-                process::Command::new("git")
-                    .arg("--git-dir")
-                    .arg(git_dir)
-                    .arg("--work-tree")
-                    .arg(&ctx.dot)
-                    .args(svalues)
-                    .arg(cmd)
-                    .args(evalues)
-                    .status()?;
+        if let (cmd, Some(sub_m)) = git.subcommand() {
+            let mut evalues = Vec::new();
+            if let Some(v) = sub_m.values_of("") {
+                evalues = v.collect::<Vec<&str>>();
             }
-            _ => {}
+            crate::git::command(ctx, git_dir)
+                .args(svalues)
+                .arg(cmd)
+                .args(evalues)
+                .status()?;
         }
         Ok(())
     }
@@ -0,0 +1,50 @@
+use super::Command;
+use crate::config::{Config, Context};
+use crate::error::{DotError, Result};
+use crate::history::Run;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct HistoryCmd {
+    /// Show changes of this run
+    #[structopt(name = "run")]
+    run: Option<String>,
+}
+
+impl Command for HistoryCmd {
+    fn run(&self, ctx: &Context, _config: &mut Config) -> Result<()> {
+        let runs = Run::list(ctx)?;
+        if let Some(id) = &self.run {
+            let run = runs
+                .iter()
+                .find(|r| &r.id == id)
+                .ok_or_else(|| DotError::UnknownRun(id.to_owned()))?;
+            println!("==> {} {} on {}:", run.id, run.direction, run.date);
+            for change in &run.changes {
+                println!("  - {} {}", change.kind(), change.path.display());
+            }
+            return Ok(());
+        }
+        if runs.is_empty() {
+            println!("==> no install or update recorded");
+        }
+        // newest first, like git log
+        for run in runs.iter().rev() {
+            let state = if run.undone {
+                " (undone)"
+            } else if run.incomplete {
+                " (not undoable)"
+            } else {
+                ""
+            };
+            println!(
+                "{} {} {} change(s){}",
+                run.id,
+                run.direction,
+                run.changes.len(),
+                state
+            );
+        }
+        Ok(())
+    }
+}
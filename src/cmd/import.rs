@@ -0,0 +1,161 @@
+use super::Command;
+use crate::config::{self, Config, Context};
+use crate::error::{DotError, Result};
+use crate::import::{self, Import, Layout, Translation};
+use crate::sync::TempDir;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct ImportCmd {
+    /// Layout to import: stow, chezmoi or yadm
+    #[structopt(long = "from")]
+    from: Layout,
+    /// Stow directory, chezmoi source directory or yadm repository
+    #[structopt(name = "path", parse(from_os_str))]
+    path: PathBuf,
+    /// Directory files are installed to, stow directory parent for stow and home otherwise
+    #[structopt(short = "t", long = "target", parse(from_os_str))]
+    target: Option<PathBuf>,
+    /// Only show what would be imported
+    #[structopt(short = "n", long = "dry-run")]
+    dry_run: bool,
+}
+
+// Why import cannot be added as an entry, if so
+fn conflict(ctx: &Context, config: &Config, import: &Import) -> Result<Option<String>> {
+    let (path, loc) = ctx.clean_path(&import.target);
+    if path.as_os_str().is_empty() {
+        return Ok(Some(format!("base directory of location {}", loc)));
+    }
+    if config.find_entry(ctx, &import.target)?.is_some() {
+        return Ok(Some("already managed".to_owned()));
+    }
+    for file in &config.files {
+        if ctx
+            .get_path(&file.location)?
+            .join(&file.path)
+            .starts_with(&import.target)
+        {
+            return Ok(Some(format!("contains managed {}", file.path.display())));
+        }
+    }
+    let stored = loc.storage(&path);
+    if ctx.dot.join(&stored).symlink_metadata().is_ok() {
+        return Ok(Some(format!(
+            "{} already exists in dot directory",
+            stored.display()
+        )));
+    }
+    Ok(None)
+}
+
+// Add translated entries and write their content to dot directory, what
+// cannot be added is reported. Source describes where entries come from.
+pub fn add_imports(
+    ctx: &Context,
+    config: &mut Config,
+    source: &str,
+    mut translation: Translation,
+    dry_run: bool,
+) -> Result<()> {
+    let mut imports: Vec<Import> = Vec::new();
+    for import in translation.imports {
+        if let Some(reason) = conflict(ctx, config, &import)? {
+            translation.skipped.push((import.target, reason));
+            continue;
+        }
+        // e.g the same file in two stow packages
+        let (path, _) = ctx.clean_path(&import.target);
+        let claimed = imports.iter().find(|i| {
+            let (other, _) = ctx.clean_path(&i.target);
+            path.starts_with(&other) || other.starts_with(&path)
+        });
+        match claimed {
+            Some(other) => {
+                let reason = format!("conflicts with {}", other.target.display());
+                translation.skipped.push((import.target, reason));
+            }
+            None => imports.push(import),
+        }
+    }
+    if imports.is_empty() {
+        println!("==> nothing to import from {}", source);
+    } else {
+        println!("==> importing from {}:", source);
+        for import in &imports {
+            println!("  - {}", import.target.display());
+        }
+    }
+    if !translation.skipped.is_empty() {
+        println!("!=> cannot import:");
+        for (path, reason) in &translation.skipped {
+            println!("  - {}: {}", path.display(), reason);
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+    for import in &imports {
+        let (path, loc) = ctx.clean_path(&import.target);
+        let mut stored = path.to_owned();
+        if let Some(entry) = import.entry.as_ref().filter(|e| e.is_glob()) {
+            // glob matches are stored under glob base
+            let tail = entry.path.strip_prefix(entry.glob_base())?;
+            for _ in tail.components() {
+                stored.pop();
+            }
+        }
+        import.write(&ctx.dot_path(&loc, &stored))?;
+        config.add_file(ctx, &import.target, import.dir)?;
+        if let Some(file) = config
+            .files
+            .iter_mut()
+            .find(|f| f.path == path && f.location == loc)
+        {
+            file.mode = import.mode;
+            // exported entry keeps its settings, location is the local one
+            if let Some(entry) = &import.entry {
+                file.recursive = entry.recursive;
+                file.symbolic = entry.symbolic;
+                file.exclude = entry.exclude.clone();
+                file.include = entry.include.clone();
+            }
+        }
+    }
+    for exclude in &translation.exclude {
+        if !config.exclude.contains(exclude) {
+            println!("==> adding global exclusion {}", exclude);
+            config.add_global_exclude(exclude)?;
+        }
+    }
+    if !imports.is_empty() {
+        println!("==> review what install would change with `dotto install --dry-run`");
+    }
+    Ok(())
+}
+
+impl ImportCmd {
+    fn import(&self, ctx: &Context, config: &mut Config, tmp: &Path) -> Result<()> {
+        let path = config::absolute(&self.path)?;
+        let target = match (&self.target, self.from) {
+            (Some(t), _) => config::absolute(t)?,
+            (None, Layout::Stow) => path.parent().unwrap_or(&path).to_owned(),
+            (None, _) => ctx.home.to_owned(),
+        };
+        let translation = import::translate(ctx, self.from, &path, &target, tmp)?;
+        let source = format!("{} {}", self.from, path.display());
+        add_imports(ctx, config, &source, translation, self.dry_run)
+    }
+}
+
+impl Command for ImportCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        // imported content is written to dot directory, there is none in bare mode
+        if ctx.bare {
+            return Err(DotError::BareMode("import".to_owned()).into());
+        }
+        let tmp = TempDir::new("import")?;
+        self.import(ctx, config, tmp.path())
+    }
+}
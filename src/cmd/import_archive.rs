@@ -0,0 +1,51 @@
+use super::{add_imports, Command};
+use crate::config::{self, Config, Context};
+use crate::error::{DotError, Result};
+use crate::import;
+use crate::sync::TempDir;
+use std::path::{Path, PathBuf};
+use std::process;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct ImportArchiveCmd {
+    /// Tar archive made by `dotto export`
+    #[structopt(name = "archive", parse(from_os_str))]
+    archive: PathBuf,
+    /// Only show what would be imported
+    #[structopt(short = "n", long = "dry-run")]
+    dry_run: bool,
+}
+
+impl ImportArchiveCmd {
+    fn import(&self, ctx: &Context, config: &mut Config, tmp: &Path) -> Result<()> {
+        let archive = config::absolute(&self.archive)?;
+        if !archive.is_file() {
+            return Err(DotError::NotFound(archive.display().to_string()).into());
+        }
+        let status = process::Command::new("tar")
+            .arg("-x")
+            .arg("-f")
+            .arg(&archive)
+            .arg("-C")
+            .arg(tmp)
+            .status()?;
+        if !status.success() {
+            return Err(DotError::TarFailed("extract".to_owned()).into());
+        }
+        // archive paths are relative to home
+        let translation = import::archive(ctx, tmp, &ctx.home)?;
+        let source = archive.display().to_string();
+        add_imports(ctx, config, &source, translation, self.dry_run)
+    }
+}
+
+impl Command for ImportArchiveCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        if ctx.bare {
+            return Err(DotError::BareMode("import-archive".to_owned()).into());
+        }
+        let tmp = TempDir::new("import-archive")?;
+        self.import(ctx, config, tmp.path())
+    }
+}
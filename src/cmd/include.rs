@@ -0,0 +1,22 @@
+use super::Command;
+use crate::config::{Config, Context};
+use crate::error::Result;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct IncludeCmd {
+    #[structopt(name = "includes")]
+    includes: Vec<String>,
+}
+
+impl Command for IncludeCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        for include in &self.includes {
+            println!("==> adding inclusion {}", include);
+            if let Err(e) = config.add_include(ctx, include) {
+                println!("!=> {}", e);
+            }
+        }
+        Ok(())
+    }
+}
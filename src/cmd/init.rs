@@ -15,6 +15,9 @@ pub struct InitCmd {
     // Override --git if enabled
     #[structopt(long = "git-dir", parse(from_os_str))]
     git_dir: Option<PathBuf>,
+    /// Use home as git work tree, files are committed in place (implies --git)
+    #[structopt(long = "bare")]
+    bare: bool,
 }
 
 impl Command for InitCmd {
@@ -23,6 +26,21 @@ impl Command for InitCmd {
         std::fs::create_dir_all(&ctx.dot)?;
         if let Some(gd) = &self.git_dir {
             config.set_git_dir(ctx, gd);
+        } else if self.bare {
+            let git_dir = ctx.dot.join("repo.git");
+            println!("==> initializing bare git directory");
+            process::Command::new("git")
+                .arg("init")
+                .arg("--bare")
+                .arg(&git_dir)
+                .status()?;
+            // home is full of unrelated files
+            process::Command::new("git")
+                .arg("--git-dir")
+                .arg(&git_dir)
+                .args(["config", "status.showUntrackedFiles", "no"])
+                .status()?;
+            config.set_git_dir(ctx, git_dir);
         } else if self.git {
             println!("==> initializing git directory");
             process::Command::new("git")
@@ -32,6 +50,9 @@ impl Command for InitCmd {
                 .status()?;
             config.set_git_dir(ctx, ctx.dot.join(".git"));
         }
+        if let (true, Some(git)) = (self.bare, config.git_mut()) {
+            git.bare = true;
+        }
         Ok(())
     }
 }
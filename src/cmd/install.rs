@@ -1,63 +1,159 @@
-use super::{Command, SyncContext};
-use crate::config::{Config, Context};
+use super::{bare, guard, plan, sync_command, Direction, SyncContext, SyncOpts};
+use crate::config::{Config, Context, SyncMode};
 use crate::error::Result;
-use crate::sync::{self, SyncSettings};
-use std::io::{self, Read, Write};
+use crate::git::Remote;
+use crate::history::Run;
+use crate::privilege::{self, Operation};
+use crate::report::Outcome;
+use crate::sync;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-pub struct InstallCmd {}
+pub struct InstallCmd {
+    #[structopt(flatten)]
+    opts: SyncOpts,
+    /// Save plan to file instead of applying it, see `dotto apply`
+    #[structopt(long = "plan-out", parse(from_os_str))]
+    plan_out: Option<PathBuf>,
+    /// Override entries sync mode: mirror, additive or prompt-deletes
+    #[structopt(long = "mode")]
+    mode: Option<SyncMode>,
+}
+
+// Copy planned changes from dot directory to system
+pub fn apply_install(
+    ctx: &Context,
+    config: &Config,
+    sync_ctx: &[SyncContext],
+    verbose: bool,
+) -> Result<()> {
+    let mut guard = guard(ctx, config)?;
+    // all deletions are checked before touching anything, privileged ones included
+    let mut count = 0;
+    for sctx in sync_ctx {
+        let dst_root = ctx.get_path(&sctx.location)?.join(&sctx.path);
+        guard.check(&dst_root, &sctx.diffs)?;
+        count += sync::count_deletions(&sctx.diffs);
+    }
+    guard.check_count(count)?;
+    let run = Run::start(ctx, Direction::Install, sync_ctx)?;
+    let res = install(ctx, config, sync_ctx, &mut guard, verbose);
+    run.finish()?;
+    res
+}
 
-// TODO: allow to copy as root
-impl Command for InstallCmd {
-    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
-        let mut sync_ctx = Vec::new();
-        for file in &config.files {
-            let src_root = ctx.dot.join(&file.path);
-            let dst_root = ctx.get_path(&file.location).join(&file.path);
-            let file_diffs = sync::sync_diff(
-                src_root,
-                &dst_root,
-                &SyncSettings::new(0, file.recursive, file.exclude.as_slice())?,
-            )?;
-            if file_diffs.is_empty() {
-                continue;
-            }
-            sync_ctx.push(SyncContext::new(
-                file.location.clone(),
-                file.path.clone(),
-                file_diffs,
-            ));
+fn install(
+    ctx: &Context,
+    config: &Config,
+    sync_ctx: &[SyncContext],
+    guard: &mut sync::Guard,
+    verbose: bool,
+) -> Result<()> {
+    let mut ops = Vec::new();
+    for sctx in sync_ctx {
+        let src_root = ctx.dot_path(&sctx.location, &sctx.path);
+        ops.extend(install_entry(ctx, &src_root, sctx, guard, false, verbose)?);
+    }
+    escalate(config, &ops, verbose)
+}
+
+// Install entry from src_root, diffs needing privileges are returned as operations.
+// Links are synced as is when diffs were computed with them.
+pub fn install_entry(
+    ctx: &Context,
+    src_root: &Path,
+    sctx: &SyncContext,
+    guard: &mut sync::Guard,
+    links: bool,
+    verbose: bool,
+) -> Result<Vec<Operation>> {
+    let dst_root = ctx.get_path(&sctx.location)?.join(&sctx.path);
+    let (diffs, privileged) = if ctx.is_system(&sctx.location) {
+        privilege::split(&dst_root, &sctx.diffs)
+    } else {
+        (sctx.diffs.clone(), Vec::new())
+    };
+    if !diffs.is_empty() {
+        if verbose {
+            println!("==> installing into {}", dst_root.display());
         }
-        if sync_ctx.is_empty() {
-            println!("==> everything is up to date");
-            return Ok(());
+        if links {
+            sync::sync_links(src_root, &dst_root, &diffs, guard)?;
+        } else {
+            sync::sync(src_root, &dst_root, &diffs, guard)?;
         }
-        println!("==> these changes will be applied:");
-        for sctx in &sync_ctx {
-            println!(
-                "  - in {}:",
-                ctx.get_path(&sctx.location).join(&sctx.path).display()
-            );
-            for diff in &sctx.diffs {
-                println!("    - {}", diff);
-            }
+    }
+    if privileged.is_empty() {
+        return Ok(Vec::new());
+    }
+    // privileged deletions are trashed and counted like others
+    guard.delegate(&dst_root, &privileged)?;
+    Ok(privilege::operations(src_root, &dst_root, &privileged))
+}
+
+// Apply privileged operations left by install_entry
+pub fn escalate(config: &Config, ops: &[Operation], verbose: bool) -> Result<()> {
+    if ops.is_empty() {
+        return Ok(());
+    }
+    if verbose {
+        println!(
+            "==> installing privileged files using {}",
+            config.escalate()
+        );
+    }
+    privilege::escalate(config.escalate(), ops)
+}
+
+// Fast forward dot repository before planning, when auto pull is enabled.
+// Returns context and config read again when something was pulled.
+fn pull(ctx: &Context, config: &Config, verbose: bool) -> Result<Option<(Context, Config)>> {
+    let (git, git_dir) = match (config.git(), config.git_dir(ctx)) {
+        (Some(git), Some(git_dir)) if git.pull => (git, git_dir),
+        _ => return Ok(None),
+    };
+    let remote = Remote::new(ctx, &git_dir, git)?;
+    if verbose {
+        println!("==> pulling from {}", remote);
+    }
+    if !remote.pull(ctx, &git_dir)? {
+        return Ok(None);
+    }
+    // pulled config can add entries and locations, they are installed now
+    let config = Config::open(ctx)?;
+    let mut ctx = ctx.clone();
+    ctx.set_locations(&config.locations)?;
+    Ok(Some((ctx, config)))
+}
+
+impl InstallCmd {
+    pub fn run(&self, ctx: &Context, config: &Config) -> Outcome {
+        if ctx.bare {
+            return bare::install_command(ctx, config, &self.opts, self.plan_out.is_some());
         }
-        let mut lock = io::stdout();
-        write!(lock, "==> confirm? [y/N]: ")?;
-        lock.flush()?;
-        let mut c = [0];
-        io::stdin().read(&mut c)?;
-        if c[0] as char == 'y' {
-            for sctx in &sync_ctx {
-                let dst_root = ctx.get_path(&sctx.location).join(&sctx.path);
-                let src_root = ctx.dot.join(&sctx.path);
-                println!("==> installing into {}", dst_root.display());
-                sync::sync(src_root, dst_root, &sctx.diffs)?;
-            }
+        // a dry run has no side effect, it plans against current dot directory
+        let pulled = if self.opts.dry_run {
+            Ok(None)
         } else {
-            println!("==> cancelled");
-        }
-        Ok(())
+            pull(ctx, config, self.opts.is_text())
+        };
+        let (pulled, reopened) = match pulled {
+            Ok(reopened) => (Ok(()), reopened),
+            Err(e) => (Err(e), None),
+        };
+        let (ctx, config) = match &reopened {
+            Some((ctx, config)) => (ctx, config),
+            None => (ctx, config),
+        };
+        sync_command(
+            ctx,
+            config,
+            Direction::Install,
+            &self.opts,
+            self.plan_out.as_deref(),
+            pulled.and_then(|_| plan(ctx, config, Direction::Install, self.mode)),
+            |sync_ctx| apply_install(ctx, config, sync_ctx, self.opts.is_text()),
+        )
     }
 }
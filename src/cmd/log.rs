@@ -0,0 +1,33 @@
+use super::Command;
+use crate::config::{Config, Context};
+use crate::error::{DotError, Result};
+use crate::git;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct LogCmd {
+    #[structopt(name = "path", parse(from_os_str))]
+    path: PathBuf,
+    /// Show changes made by each commit
+    #[structopt(short = "p", long = "patch")]
+    patch: bool,
+}
+
+impl Command for LogCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        let git_dir = config.git_dir(ctx).ok_or(DotError::NoGitDir)?;
+        let (_, path) = config.repo_path(ctx, &self.path)?;
+        let mut cmd = git::command(ctx, git_dir);
+        cmd.current_dir(ctx.work_tree()).arg("log");
+        if self.patch {
+            cmd.arg("--patch");
+        }
+        // renames can only be followed for a single file
+        if !ctx.work_tree().join(&path).is_dir() {
+            cmd.arg("--follow");
+        }
+        cmd.arg("--").arg(&path).status()?;
+        Ok(())
+    }
+}
@@ -1,23 +1,68 @@
 mod add;
+mod adopt;
+mod apply;
+mod apply_privileged;
+mod bare;
+mod check;
+mod config;
 mod edit;
 mod exclude;
+mod export;
 mod git;
+mod history;
+mod import;
+mod import_archive;
+mod include;
 mod init;
 mod install;
+mod log;
+mod mv;
+mod relocate;
+mod remote;
+mod restore;
+mod rm;
+mod status;
+mod undo;
 mod update;
 
 pub use self::add::*;
+pub use self::adopt::*;
+pub use self::apply::*;
+pub use self::apply_privileged::*;
+pub use self::check::*;
+pub use self::config::*;
 pub use self::edit::*;
 pub use self::exclude::*;
+pub use self::export::*;
 pub use self::git::*;
+pub use self::history::*;
+pub use self::import::*;
+pub use self::import_archive::*;
+pub use self::include::*;
 pub use self::init::*;
 pub use self::install::*;
+pub use self::log::*;
+pub use self::mv::*;
+pub use self::relocate::*;
+pub use self::remote::*;
+pub use self::restore::*;
+pub use self::rm::*;
+pub use self::status::*;
+pub use self::undo::*;
 pub use self::update::*;
 
-use crate::config::{Config, Context, Location};
+use crate::config::{Config, Context, File, Location, SyncMode};
 use crate::error::Result;
-use crate::sync::Diff;
-use std::path::PathBuf;
+use crate::plan::Plan;
+use crate::privilege;
+use crate::report::{Format, Outcome, Report, ReportDiff, ReportEntry};
+use crate::sync::{self, Diff, DiffKind};
+use crate::trash::Trash;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
 
 pub trait Command {
     fn run(&self, ctx: &Context, config: &mut Config) -> Result<()>;
@@ -27,6 +72,9 @@ pub struct SyncContext {
     pub location: Location,
     pub path: PathBuf,
     pub diffs: Vec<Diff>,
+    pub mode: SyncMode,
+    // changes suppressed by sync mode, never applied
+    pub skipped: Vec<Diff>,
 }
 
 impl SyncContext {
@@ -35,6 +83,388 @@ impl SyncContext {
             location,
             path,
             diffs,
+            mode: SyncMode::Mirror,
+            skipped: Vec::new(),
         }
     }
+
+    pub fn with_mode(mut self, mode: SyncMode) -> SyncContext {
+        self.mode = mode;
+        if mode == SyncMode::Additive {
+            let (diffs, skipped) = sync::skip_deletions(std::mem::take(&mut self.diffs));
+            self.diffs = diffs;
+            self.skipped.extend(skipped);
+        }
+        self
+    }
+
+    // Top most deletions, a deleted directory covers everything inside it
+    pub fn deletions(&self) -> Vec<&Path> {
+        let deleted = self
+            .diffs
+            .iter()
+            .filter(|d| *d.kind() == DiffKind::Deleted)
+            .map(|d| d.path())
+            .collect::<Vec<_>>();
+        deleted
+            .iter()
+            .filter(|p| !deleted.iter().any(|o| o != *p && p.starts_with(o)))
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diffs.is_empty() && self.skipped.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    // from dot directory to system
+    Install,
+    // from system to dot directory
+    Update,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::Install => write!(f, "install"),
+            Direction::Update => write!(f, "update"),
+        }
+    }
+}
+
+// Returns (src, dst) roots for path in given direction
+pub fn roots<P: AsRef<Path>>(
+    ctx: &Context,
+    location: &Location,
+    path: P,
+    direction: Direction,
+) -> Result<(PathBuf, PathBuf)> {
+    let dot = ctx.dot_path(location, path.as_ref());
+    let system = ctx.get_path(location)?.join(path.as_ref());
+    match direction {
+        Direction::Install => Ok((dot, system)),
+        Direction::Update => Ok((system, dot)),
+    }
+}
+
+// Deletion guard for a run, protects home, locations bases and dot directory
+pub fn guard(ctx: &Context, config: &Config) -> Result<sync::Guard> {
+    let mut protected = vec![ctx.home.to_owned(), ctx.dot.to_owned()];
+    protected.extend(ctx.bases().into_iter().map(|(_, p)| p.to_owned()));
+    protected.push(ctx.get_path(&Location::Absolute)?);
+    protected.extend(ctx.root.clone());
+    let trash = if config.trash() {
+        Some(Trash::new(ctx.xdg_data.join("Trash")))
+    } else {
+        None
+    };
+    Ok(sync::Guard::new(protected, config.max_deletions(), trash))
+}
+
+// Expand glob entry against source side, each match is synced on its own
+fn plan_glob(
+    ctx: &Context,
+    config: &Config,
+    file: &File,
+    direction: Direction,
+    mode: SyncMode,
+) -> Result<Vec<SyncContext>> {
+    let (src_base, dst_base) = roots(ctx, &file.location, "", direction)?;
+    let settings = config.settings(file)?;
+    let matches = file.expand(&src_base, &settings)?;
+    // only reported when system is the source, on install dot directory matches
+    // missing from system are just not installed yet
+    let vanished = match direction {
+        Direction::Update => file.expand(&dst_base, &settings)?,
+        Direction::Install => Vec::new(),
+    };
+    for vanished in vanished {
+        if !matches.contains(&vanished) {
+            // warnings go to stderr, stdout may be machine readable
+            eprintln!(
+                "!=> {} no longer matches {}",
+                src_base.join(&vanished).display(),
+                file.path.display()
+            );
+        }
+    }
+    let base = file.glob_base();
+    let mut sync_ctx = Vec::new();
+    for path in matches {
+        // excludes are relative to glob base, like directory entries
+        let prefix = path.strip_prefix(&base).unwrap_or(&path).to_owned();
+        let settings = config.settings(file)?.prefix(prefix);
+        let diffs = sync::sync_diff(src_base.join(&path), dst_base.join(&path), &settings)?;
+        let sctx = SyncContext::new(file.location.clone(), path, diffs).with_mode(mode);
+        if !sctx.is_empty() {
+            sync_ctx.push(sctx);
+        }
+    }
+    Ok(sync_ctx)
+}
+
+// Compute diffs of all entries in given direction, mode overrides entries sync mode
+pub fn plan(
+    ctx: &Context,
+    config: &Config,
+    direction: Direction,
+    mode: Option<SyncMode>,
+) -> Result<Vec<SyncContext>> {
+    let mut sync_ctx = Vec::new();
+    for file in &config.files {
+        let mode = mode.unwrap_or(file.mode);
+        if file.is_glob() {
+            sync_ctx.extend(plan_glob(ctx, config, file, direction, mode)?);
+            continue;
+        }
+        let (src_root, dst_root) = roots(ctx, &file.location, &file.path, direction)?;
+        // one missing entry does not prevent syncing the others
+        if !src_root.exists() {
+            eprintln!("!=> {} is missing, skipping it", src_root.display());
+            continue;
+        }
+        let file_diffs = sync::sync_diff(src_root, &dst_root, &config.settings(file)?)?;
+        let sctx =
+            SyncContext::new(file.location.clone(), file.path.clone(), file_diffs).with_mode(mode);
+        if !sctx.is_empty() {
+            sync_ctx.push(sctx);
+        }
+    }
+    Ok(sync_ctx)
+}
+
+// Options shared by install and update
+#[derive(Debug, StructOpt)]
+pub struct SyncOpts {
+    /// Apply changes without asking for confirmation
+    #[structopt(short = "y", long = "yes")]
+    pub yes: bool,
+    /// Show changes without applying them
+    #[structopt(short = "n", long = "dry-run")]
+    pub dry_run: bool,
+    /// Output format: text or json (plan and result, nothing else on stdout)
+    #[structopt(long = "format", default_value = "text")]
+    pub format: Format,
+}
+
+// Ask a yes/no question, no is the default
+pub fn ask(question: &str, format: &Format) -> Result<bool> {
+    // keep stdout clean for json output
+    let mut lock: Box<dyn Write> = match format {
+        Format::Text => Box::new(io::stdout()),
+        Format::Json => Box::new(io::stderr()),
+    };
+    write!(lock, "==> {} [y/N]: ", question)?;
+    lock.flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.starts_with('y'))
+}
+
+impl SyncOpts {
+    pub fn is_text(&self) -> bool {
+        self.format == Format::Text
+    }
+
+    fn ask(&self, question: &str) -> Result<bool> {
+        ask(question, &self.format)
+    }
+
+    fn confirm(&self) -> Result<bool> {
+        if self.yes {
+            return Ok(true);
+        }
+        self.ask("confirm?")
+    }
+
+    // Ask for each deletion of prompt-deletes entries, refused ones are skipped.
+    // Nothing is deleted without an answer (--yes).
+    fn prompt_deletes(
+        &self,
+        ctx: &Context,
+        direction: Direction,
+        sync_ctx: &mut [SyncContext],
+    ) -> Result<()> {
+        for sctx in sync_ctx {
+            if sctx.mode != SyncMode::PromptDeletes {
+                continue;
+            }
+            let (_, dst) = roots(ctx, &sctx.location, &sctx.path, direction)?;
+            let mut refused = Vec::new();
+            for path in sctx.deletions() {
+                let diff = Diff::new(path, DiffKind::Deleted);
+                let question = format!("delete {}?", diff.full_path(&dst).display());
+                if self.yes || !self.ask(&question)? {
+                    refused.push(path.to_owned());
+                }
+            }
+            if self.yes && !refused.is_empty() && self.is_text() {
+                println!(
+                    "==> {} deletions in {} kept, they need confirmation",
+                    refused.len(),
+                    dst.display()
+                );
+            }
+            let (diffs, skipped) = sync::skip_paths(std::mem::take(&mut sctx.diffs), &refused);
+            sctx.diffs = diffs;
+            sctx.skipped.extend(skipped);
+        }
+        Ok(())
+    }
+}
+
+fn report_plan(
+    ctx: &Context,
+    sync_ctx: &[SyncContext],
+    direction: Direction,
+) -> Result<Vec<ReportEntry>> {
+    let mut entries = Vec::new();
+    for sctx in sync_ctx {
+        let (src, dst) = roots(ctx, &sctx.location, &sctx.path, direction)?;
+        let mut diffs = sctx
+            .diffs
+            .iter()
+            .map(|diff| ReportDiff {
+                kind: diff.kind().clone(),
+                path: diff.path().to_owned(),
+                privileged: direction == Direction::Install
+                    && ctx.is_system(&sctx.location)
+                    && privilege::needs_privilege(&dst, diff),
+                skipped: false,
+            })
+            .collect::<Vec<_>>();
+        diffs.extend(sctx.skipped.iter().map(|diff| ReportDiff {
+            kind: diff.kind().clone(),
+            path: diff.path().to_owned(),
+            privileged: false,
+            skipped: true,
+        }));
+        entries.push(ReportEntry {
+            location: sctx.location.clone(),
+            path: sctx.path.clone(),
+            src,
+            dst,
+            diffs,
+        });
+    }
+    Ok(entries)
+}
+
+fn print_plan(config: &Config, report: &Report) {
+    println!("==> these changes will be applied:");
+    let mut privileged = 0;
+    for entry in &report.plan {
+        // entries are always shown by their system path
+        let system = match report.direction {
+            Direction::Install => &entry.dst,
+            Direction::Update => &entry.src,
+        };
+        println!("  - in {}:", system.display());
+        for diff in &entry.diffs {
+            let diff_str = Diff::new(diff.path.as_path(), diff.kind.clone()).to_string();
+            if diff.skipped {
+                println!("    - {} (skipped)", diff_str);
+            } else if diff.privileged {
+                privileged += 1;
+                println!("    - {} (privileged)", diff_str);
+            } else {
+                println!("    - {}", diff_str);
+            }
+        }
+    }
+    if privileged > 0 {
+        println!(
+            "==> {} changes require elevated privileges (using {})",
+            privileged,
+            config.escalate()
+        );
+    }
+}
+
+fn run_sync<F>(
+    ctx: &Context,
+    config: &Config,
+    opts: &SyncOpts,
+    plan_out: Option<&Path>,
+    sync_ctx: Result<Vec<SyncContext>>,
+    report: &mut Report,
+    apply: F,
+) -> Result<Outcome>
+where
+    F: FnOnce(&[SyncContext]) -> Result<()>,
+{
+    let direction = report.direction;
+    let mut sync_ctx = sync_ctx?;
+    report.plan = report_plan(ctx, &sync_ctx, direction)?;
+    if sync_ctx.iter().all(|s| s.diffs.is_empty()) {
+        if opts.is_text() {
+            if !sync_ctx.is_empty() {
+                print_plan(config, report);
+            }
+            println!("==> everything is up to date");
+        }
+        return Ok(Outcome::NothingToDo);
+    }
+    if opts.is_text() {
+        print_plan(config, report);
+    }
+    if let Some(path) = plan_out {
+        Plan::new(ctx, direction, &sync_ctx)?.write(path)?;
+        if opts.is_text() {
+            println!("==> plan saved to {}", path.display());
+        }
+        return Ok(Outcome::Planned);
+    }
+    if opts.dry_run {
+        return Ok(Outcome::Planned);
+    }
+    if !opts.confirm()? {
+        if opts.is_text() {
+            println!("==> cancelled");
+        }
+        return Ok(Outcome::Cancelled);
+    }
+    opts.prompt_deletes(ctx, direction, &mut sync_ctx)?;
+    report.plan = report_plan(ctx, &sync_ctx, direction)?;
+    apply(&sync_ctx)?;
+    Ok(Outcome::Applied)
+}
+
+// Show planned changes in given direction, then save them to plan_out or
+// confirm and apply them. Errors are reported here, returned outcome gives the exit code.
+pub fn sync_command<F>(
+    ctx: &Context,
+    config: &Config,
+    direction: Direction,
+    opts: &SyncOpts,
+    plan_out: Option<&Path>,
+    sync_ctx: Result<Vec<SyncContext>>,
+    apply: F,
+) -> Outcome
+where
+    F: FnOnce(&[SyncContext]) -> Result<()>,
+{
+    let mut report = Report::new(direction);
+    let outcome = match run_sync(ctx, config, opts, plan_out, sync_ctx, &mut report, apply) {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            if opts.is_text() {
+                println!("!=> {}", e);
+            }
+            report.error = Some(e.to_string());
+            Outcome::Failed
+        }
+    };
+    report.finish(outcome);
+    if !opts.is_text() {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("!=> {}", e),
+        }
+    }
+    outcome
 }
@@ -0,0 +1,81 @@
+use super::Command;
+use crate::config::{self, Config, Context};
+use crate::error::{DotError, Result};
+use crate::git;
+use crate::sync;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct MvCmd {
+    #[structopt(name = "old", parse(from_os_str))]
+    old: PathBuf,
+    #[structopt(name = "new", parse(from_os_str))]
+    new: PathBuf,
+    /// Do not commit changes
+    #[structopt(long = "no-commit")]
+    no_commit: bool,
+}
+
+// Returns false if there was nothing to move
+fn rename<A: AsRef<Path>, B: AsRef<Path>>(old: A, new: B) -> Result<bool> {
+    let (old, new) = (old.as_ref(), new.as_ref());
+    if old.symlink_metadata().is_err() {
+        return Ok(false);
+    }
+    if new.symlink_metadata().is_ok() {
+        return Err(DotError::AlreadyExists(new.display().to_string()).into());
+    }
+    println!("==> moving {} to {}", old.display(), new.display());
+    // old and new can be on different file systems (e.g /etc and dot directory)
+    sync::move_path(old, new)?;
+    Ok(true)
+}
+
+impl Command for MvCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        let (old, new) = config.move_path(ctx, &self.old, &self.new)?;
+        let (dot_old, dot_new) = (ctx.dot.join(&old), ctx.dot.join(&new));
+        if dot_new.symlink_metadata().is_ok() {
+            return Err(DotError::AlreadyExists(dot_new.display().to_string()).into());
+        }
+        // paths to commit, relative to work tree
+        let mut paths = config::shared_paths(ctx);
+        if ctx.bare {
+            let moved = [
+                ctx.home_path(&config::absolute(&self.old)?)?,
+                ctx.home_path(&config::absolute(&self.new)?)?,
+            ];
+            rename(&self.old, &self.new)?;
+            if let Some(git_dir) = config.git_dir(ctx) {
+                git::add(ctx, git_dir, &moved)?;
+            }
+        } else {
+            paths.push(old.to_owned());
+            paths.push(new.to_owned());
+            let moved = rename(&self.old, &self.new)?;
+            if let Err(e) = rename(&dot_old, &dot_new) {
+                // system path is put back, nothing has moved then
+                if moved {
+                    rename(&self.new, &self.old)?;
+                }
+                return Err(e);
+            }
+        }
+        if self.no_commit {
+            return Ok(());
+        }
+        if let Some(git_dir) = config.git_dir(ctx) {
+            // config must be written before being committed
+            config.save(&ctx.dot_config)?;
+            let message = format!("mv {} {}\n", old.display(), new.display());
+            if ctx.bare {
+                // moved files are already staged, other files in home are left alone
+                git::commit_staged(ctx, git_dir, &message, &paths)?;
+            } else {
+                git::commit_paths(ctx, git_dir, &message, &paths)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use crate::check::{self, Severity};
+use crate::config::{self, Config, Context};
+use crate::error::{DotError, Result};
+use crate::report::Outcome;
+use crate::sync;
+use std::env;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct RelocateCmd {
+    #[structopt(name = "new_dir", parse(from_os_str))]
+    new: PathBuf,
+}
+
+impl RelocateCmd {
+    // Dot directory is moved even if check fails, failure is reported through exit code
+    pub fn run(&self, ctx: &Context, config: &mut Config) -> Result<Outcome> {
+        let old = ctx.dot.to_owned();
+        let new = config::absolute(&self.new)?;
+        if new.starts_with(&old) {
+            return Err(DotError::InvalidRelocation(new.display().to_string()).into());
+        }
+        if new.symlink_metadata().is_ok() {
+            return Err(DotError::AlreadyExists(new.display().to_string()).into());
+        }
+        let git_dir = config.git_dir(ctx);
+        println!("==> moving {} to {}", old.display(), new.display());
+        sync::move_path(&old, &new)?;
+        let mut new_ctx = ctx.clone();
+        new_ctx.set_dot(&new)?;
+        if ctx.dot_config.starts_with(&old) {
+            new_ctx.dot_config = new.join(ctx.dot_config.strip_prefix(&old)?);
+        }
+        // a git dir inside dot directory has been moved along
+        if let Some(git_dir) = git_dir {
+            match git_dir.strip_prefix(&old) {
+                Ok(rest) => config.set_git_dir(&new_ctx, new.join(rest)),
+                Err(_) => println!("==> git dir {} is kept in place", git_dir.display()),
+            }
+        }
+        config.rebase_layers(&old, &new);
+        // `dot` is machine specific, it is only updated in local or user config
+        let origin = config.setting_origin("dot").map(Path::to_owned);
+        let user = config::user_config(ctx);
+        if origin == Some(config::local_config(&new_ctx)) || origin.as_ref() == Some(&user) {
+            config.dot = Some(new.to_owned());
+        }
+        config.save(&new_ctx.dot_config)?;
+        if env::var_os("DOT_PATH").is_some() {
+            println!("!=> DOT_PATH is set, update it to {}", new.display());
+        } else if origin.as_ref() != Some(&user) {
+            // a local config has moved along, only user config is read to find it
+            println!("==> recording dot directory in {}", user.display());
+            Config::save_user_dot(ctx, &new)?;
+        }
+        let issues = check::check(&new_ctx, config);
+        for issue in &issues {
+            println!("!=> {}", issue);
+        }
+        let errors = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count();
+        if errors > 0 {
+            println!("!=> config check failed with {} error(s)", errors);
+            return Ok(Outcome::Failed);
+        }
+        Ok(Outcome::Applied)
+    }
+}
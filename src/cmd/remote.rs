@@ -0,0 +1,102 @@
+use super::Command;
+use crate::config::{Config, Context};
+use crate::error::{DotError, Result};
+use crate::git::{Divergence, Remote};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum RemoteCmd {
+    /// Set remote (name or url) dot repository is synced with
+    #[structopt(name = "set")]
+    Set {
+        remote: String,
+        /// Branch to sync, current one by default
+        #[structopt(short = "b", long = "branch")]
+        branch: Option<String>,
+        /// Push after each update commit
+        #[structopt(long = "push")]
+        push: bool,
+        /// Pull before each install
+        #[structopt(long = "pull")]
+        pull: bool,
+    },
+    /// Stop syncing with remote
+    #[structopt(name = "unset")]
+    Unset {},
+    /// Show remote and how local branch compares to it
+    #[structopt(name = "status")]
+    Status {},
+    /// Fast forward dot repository to remote branch
+    #[structopt(name = "pull")]
+    Pull {},
+    /// Push dot repository to remote branch
+    #[structopt(name = "push")]
+    Push {},
+}
+
+impl Command for RemoteCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        let git_dir = config.git_dir(ctx).ok_or(DotError::NoGitDir)?;
+        let git = config.git_mut().ok_or(DotError::NoGitDir)?;
+        match self {
+            RemoteCmd::Set {
+                remote,
+                branch,
+                push,
+                pull,
+            } => {
+                git.remote = Some(remote.to_owned());
+                git.branch = branch.clone();
+                git.push = *push;
+                git.pull = *pull;
+                return Ok(());
+            }
+            RemoteCmd::Unset {} => {
+                git.remote = None;
+                git.branch = None;
+                git.push = false;
+                git.pull = false;
+                return Ok(());
+            }
+            _ => {}
+        }
+        let remote = Remote::new(ctx, &git_dir, git)?;
+        match self {
+            RemoteCmd::Status {} => {
+                println!("==> remote: {}", remote);
+                println!(
+                    "==> auto push: {}, auto pull: {}",
+                    if git.push { "on" } else { "off" },
+                    if git.pull { "on" } else { "off" }
+                );
+                match remote.fetch(ctx, &git_dir)? {
+                    Divergence::Missing => println!("==> remote branch does not exist yet"),
+                    Divergence::Unborn => println!("==> nothing committed yet"),
+                    Divergence::Commits(0, 0) => println!("==> up to date"),
+                    Divergence::Commits(local, 0) => {
+                        println!("==> {} local commit(s) to push", local)
+                    }
+                    Divergence::Commits(0, remote) => {
+                        println!("==> {} remote commit(s) to pull", remote)
+                    }
+                    Divergence::Commits(local, remote) => println!(
+                        "!=> diverged: {} local and {} remote commit(s), merge them with `dotto git pull`",
+                        local, remote
+                    ),
+                }
+            }
+            RemoteCmd::Pull {} => {
+                if !remote.pull(ctx, &git_dir)? {
+                    println!("==> already up to date");
+                }
+            }
+            RemoteCmd::Push {} => {
+                if !remote.push(ctx, &git_dir)? {
+                    println!("==> nothing to push");
+                }
+            }
+            RemoteCmd::Set { .. } | RemoteCmd::Unset {} => unreachable!(),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use super::install::{self, install_entry};
+use super::{ask, guard, Command, Direction, SyncContext};
+use crate::config::{Config, Context, File};
+use crate::error::{DotError, Result};
+use crate::git;
+use crate::history::Run;
+use crate::report::Format;
+use crate::sync::{self, TempDir};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct RestoreCmd {
+    /// Revision to restore (e.g HEAD~2 or a commit from `dotto log`)
+    #[structopt(long = "rev")]
+    rev: String,
+    #[structopt(name = "path", parse(from_os_str))]
+    path: PathBuf,
+    /// Do not ask for confirmation
+    #[structopt(short = "y", long = "yes")]
+    yes: bool,
+}
+
+impl RestoreCmd {
+    // Install path as extracted from rev into tmp
+    fn restore(
+        &self,
+        ctx: &Context,
+        config: &Config,
+        file: &File,
+        path: &Path,
+        tmp: &Path,
+    ) -> Result<()> {
+        let git_dir = config.git_dir(ctx).ok_or(DotError::NoGitDir)?;
+        if !git::checkout_to(ctx, git_dir, &self.rev, path, tmp)? {
+            return Err(DotError::NotInRev(path.display().to_string(), self.rev.to_owned()).into());
+        }
+        let (rel, location) = ctx.abs_clean_path(&self.path)?;
+        let src = tmp.join(path);
+        let dst = ctx.get_path(&location)?.join(&rel);
+        // excludes are relative to entry root
+        let base = file.location.storage(file.glob_base());
+        let prefix = path.strip_prefix(&base).unwrap_or(path);
+        // symlinks are restored as they were committed
+        let settings = config.settings(file)?.prefix(prefix).links();
+        let diffs = sync::sync_diff(&src, &dst, &settings)?;
+        if diffs.is_empty() {
+            println!("==> {} is already at {}", dst.display(), self.rev);
+            return Ok(());
+        }
+        println!("==> restoring {} from {}:", dst.display(), self.rev);
+        for diff in &diffs {
+            println!("  - {}", diff);
+        }
+        if !self.yes && !ask("confirm?", &Format::Text)? {
+            return Ok(());
+        }
+        let sctx = SyncContext::new(location, rel, diffs);
+        let mut guard = guard(ctx, config)?;
+        guard.check_count(sync::count_deletions(&sctx.diffs))?;
+        let run = Run::start(ctx, Direction::Install, std::slice::from_ref(&sctx))?;
+        // privileged files are restored like they are installed
+        let res = install_entry(ctx, &src, &sctx, &mut guard, true, false)
+            .and_then(|ops| install::escalate(config, &ops, true));
+        run.finish()?;
+        res
+    }
+}
+
+impl Command for RestoreCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        let (file, path) = config.repo_path(ctx, &self.path)?;
+        let tmp = TempDir::new("restore")?;
+        self.restore(ctx, config, file, &path, tmp.path())
+    }
+}
@@ -0,0 +1,107 @@
+use super::Command;
+use crate::config::{self, Config, Context, Removed};
+use crate::error::Result;
+use crate::git;
+use crate::sync::{self, SyncSettings};
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct RmCmd {
+    #[structopt(name = "files", parse(from_os_str))]
+    files: Vec<PathBuf>,
+    /// Delete copy from dot directory
+    #[structopt(short = "d", long = "delete")]
+    delete: bool,
+    /// Replace installed symlinks with a copy of their target
+    #[structopt(long = "restore")]
+    restore: bool,
+    /// Do not commit changes
+    #[structopt(long = "no-commit")]
+    no_commit: bool,
+}
+
+fn remove<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// Replace symlink at dst by a real copy of src
+fn restore<A: AsRef<Path>, B: AsRef<Path>>(src: A, dst: B) -> Result<()> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    match dst.symlink_metadata() {
+        Ok(meta) if meta.file_type().is_symlink() => {}
+        _ => return Ok(()),
+    }
+    println!("==> restoring {}", dst.display());
+    fs::remove_file(dst)?;
+    let diffs = sync::sync_diff(src, dst, &SyncSettings::new(0, true, &[])?)?;
+    sync::sync(src, dst, &diffs, &mut sync::Guard::default())
+}
+
+impl Command for RmCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        let mut removed = Vec::new();
+        // paths to commit, relative to work tree
+        let mut paths = config::shared_paths(ctx);
+        for file in &self.files {
+            let (dot_path, sys_path) = match config.remove_path(ctx, file)? {
+                Removed::Entry(f) => {
+                    println!("==> removing {}", f.path.display());
+                    let dst = ctx.get_path(&f.location)?.join(&f.path);
+                    (ctx.dot_path(&f.location, &f.path), dst)
+                }
+                Removed::Excluded { entry, path } => {
+                    println!(
+                        "==> excluding {} from {}",
+                        path.display(),
+                        entry.path.display()
+                    );
+                    (
+                        ctx.dot_path(&entry.location, &entry.path).join(&path),
+                        file.to_owned(),
+                    )
+                }
+            };
+            // files stay in home, they are only untracked
+            if ctx.bare {
+                if let Some(git_dir) = config.git_dir(ctx) {
+                    let path = ctx.home_path(&config::absolute(&sys_path)?)?;
+                    git::untrack(ctx, git_dir, &[path])?;
+                }
+                removed.push(file.display().to_string());
+                continue;
+            }
+            paths.push(dot_path.strip_prefix(&ctx.dot)?.to_owned());
+            if self.restore {
+                restore(&dot_path, &sys_path)?;
+            }
+            if self.delete {
+                println!("==> deleting {}", dot_path.display());
+                remove(&dot_path)?;
+            }
+            removed.push(file.display().to_string());
+        }
+        if removed.is_empty() || self.no_commit {
+            return Ok(());
+        }
+        if let Some(git_dir) = config.git_dir(ctx) {
+            // config must be written before being committed
+            config.save(&ctx.dot_config)?;
+            let message = format!("rm {}\n", removed.join(" "));
+            if ctx.bare {
+                // untracked files are kept in home, only the index has them removed
+                git::commit_staged(ctx, git_dir, &message, &paths)?;
+            } else {
+                git::commit_paths(ctx, git_dir, &message, &paths)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use super::{bare, plan, Command, Direction};
+use crate::config::{Config, Context};
+use crate::error::Result;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct StatusCmd {
+    /// Also list untracked files in managed directories (bare mode)
+    #[structopt(short = "u", long = "untracked")]
+    untracked: bool,
+}
+
+impl Command for StatusCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        if ctx.bare {
+            return bare::status(ctx, config, self.untracked);
+        }
+        // changes made on system since last sync, as update would record them
+        let sync_ctx = plan(ctx, config, Direction::Update, None)?;
+        if sync_ctx.is_empty() {
+            println!("==> everything is up to date");
+            return Ok(());
+        }
+        println!("==> system differs from dot directory:");
+        for sctx in &sync_ctx {
+            println!(
+                "  - in {}:",
+                ctx.get_path(&sctx.location)?.join(&sctx.path).display()
+            );
+            for diff in &sctx.diffs {
+                println!("    - {}", diff);
+            }
+            for diff in &sctx.skipped {
+                println!("    - {} (skipped)", diff);
+            }
+        }
+        Ok(())
+    }
+}
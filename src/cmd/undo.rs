@@ -0,0 +1,77 @@
+use super::{ask, Command};
+use crate::config::{Config, Context};
+use crate::error::{DotError, Result};
+use crate::history::Run;
+use crate::privilege::{self, Operation};
+use crate::report::Format;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct UndoCmd {
+    /// Run to revert (see `dotto history`), last one by default
+    #[structopt(name = "run")]
+    run: Option<String>,
+    /// Do not ask for confirmation
+    #[structopt(short = "y", long = "yes")]
+    yes: bool,
+    /// Only show what would be restored
+    #[structopt(short = "n", long = "dry-run")]
+    dry_run: bool,
+}
+
+impl Command for UndoCmd {
+    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
+        let runs = Run::list(ctx)?;
+        let mut run = match &self.run {
+            Some(id) => runs
+                .into_iter()
+                .find(|r| &r.id == id)
+                .ok_or_else(|| DotError::UnknownRun(id.to_owned()))?,
+            None => runs
+                .into_iter()
+                .rev()
+                .find(|r| !r.undone)
+                .ok_or(DotError::NothingToUndo)?,
+        };
+        if run.undone {
+            return Err(DotError::AlreadyUndone(run.id).into());
+        }
+        if run.incomplete {
+            return Err(DotError::NotUndoable(run.id).into());
+        }
+        // restoring before-images would lose changes made after this run
+        let changed = run.changed()?;
+        if !changed.is_empty() {
+            for path in &changed {
+                println!("!=> {} changed since run {}", path.display(), run.id);
+            }
+            return Err(DotError::ChangedSinceRun(run.id.to_owned()).into());
+        }
+        let ops = run.undo_operations();
+        if ops.is_empty() {
+            println!("==> nothing to undo");
+            return Ok(());
+        }
+        println!(
+            "==> reverting {} {} of {}:",
+            run.direction, run.id, run.date
+        );
+        for change in run.changes.iter().rev() {
+            if change.before != change.after {
+                println!("  - {} {}", change.kind(), change.path.display());
+            }
+        }
+        if self.dry_run || !(self.yes || ask("confirm?", &Format::Text)?) {
+            return Ok(());
+        }
+        let (ops, privileged): (Vec<Operation>, Vec<Operation>) = ops
+            .into_iter()
+            .partition(|op| !privilege::operation_needs_privilege(op));
+        privilege::apply(&ops)?;
+        if !privileged.is_empty() {
+            println!("==> restoring privileged files using {}", config.escalate());
+            privilege::escalate(config.escalate(), &privileged)?;
+        }
+        run.mark_undone()
+    }
+}
@@ -1,97 +1,183 @@
-use super::{Command, SyncContext};
-use crate::config::{Config, Context};
+use super::{bare, guard, plan, sync_command, Direction, SyncContext, SyncOpts};
+use crate::config::{self, Config, Context, Git, Granularity, SyncMode};
 use crate::error::Result;
-use crate::sync::{self, SyncSettings};
-use std::io::{self, Read, Write};
-use std::process;
+use crate::git::{self, Remote};
+use crate::history::Run;
+use crate::report::Outcome;
+use crate::sync::{self, Diff};
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-pub struct UpdateCmd {}
+pub struct UpdateCmd {
+    #[structopt(flatten)]
+    opts: SyncOpts,
+    /// Save plan to file instead of applying it, see `dotto apply`
+    #[structopt(long = "plan-out", parse(from_os_str))]
+    plan_out: Option<PathBuf>,
+    /// Override entries sync mode: mirror, additive or prompt-deletes
+    #[structopt(long = "mode")]
+    mode: Option<SyncMode>,
+}
 
-impl Command for UpdateCmd {
-    fn run(&self, ctx: &Context, config: &mut Config) -> Result<()> {
-        let mut sync_ctx = Vec::new();
-        for file in &config.files {
-            let src_root = ctx.get_path(&file.location).join(&file.path);
-            let dst_root = ctx.dot.join(&file.path);
-            let file_diffs = sync::sync_diff(
-                src_root,
-                &dst_root,
-                &SyncSettings::new(0, file.recursive, file.exclude.as_slice())?,
-            )?;
-            if file_diffs.is_empty() {
-                continue;
-            }
-            sync_ctx.push(SyncContext::new(
-                file.location.clone(),
-                file.path.clone(),
-                file_diffs,
-            ));
+// Copy planned changes from system to dot directory and commit them
+pub fn apply_update(
+    ctx: &Context,
+    config: &Config,
+    sync_ctx: &[SyncContext],
+    verbose: bool,
+) -> Result<()> {
+    // push could only fail once changes are applied and committed, refuse early
+    let remote = match (config.git(), config.git_dir(ctx)) {
+        (Some(git), Some(git_dir)) if git.push && git.commit != Granularity::None => {
+            let remote = Remote::new(ctx, &git_dir, git)?;
+            remote.check_push(ctx, &git_dir)?;
+            Some(remote)
         }
-        if sync_ctx.is_empty() {
-            println!("==> everything is up to date");
-            return Ok(());
+        _ => None,
+    };
+    let mut guard = guard(ctx, config)?;
+    let mut count = 0;
+    for sctx in sync_ctx {
+        guard.check(ctx.dot_path(&sctx.location, &sctx.path), &sctx.diffs)?;
+        count += sync::count_deletions(&sctx.diffs);
+    }
+    guard.check_count(count)?;
+    let run = Run::start(ctx, Direction::Update, sync_ctx)?;
+    let res = sync_ctx.iter().try_for_each(|sctx| {
+        let src_root = ctx.get_path(&sctx.location)?.join(&sctx.path);
+        let dst_root = ctx.dot_path(&sctx.location, &sctx.path);
+        if verbose {
+            println!("==> updating {}", dst_root.display());
         }
-        println!("==> these changes will be applied:");
-        for sctx in &sync_ctx {
-            println!(
-                "  - in {}:",
-                ctx.get_path(&sctx.location).join(&sctx.path).display()
+        sync::sync(src_root, dst_root, &sctx.diffs, &mut guard)
+    });
+    run.finish()?;
+    res?;
+    if let (Some(git), Some(git_dir)) = (config.git(), config.git_dir(ctx)) {
+        if let Err(e) = commit(ctx, config, git, &git_dir, sync_ctx) {
+            // dot directory is already updated, only the commit is missing
+            eprintln!(
+                "!=> changes applied to {} but not committed, commit them with `dotto git add` and `dotto git commit`",
+                ctx.dot.display()
             );
-            for diff in &sctx.diffs {
-                println!("    - {}", diff);
-            }
+            return Err(e);
         }
-        let mut lock = io::stdout();
-        write!(lock, "==> confirm? [y/N]: ")?;
-        lock.flush()?;
-        let mut c = [0];
-        io::stdin().read(&mut c)?;
-        if c[0] as char == 'y' {
-            for sctx in &sync_ctx {
-                let src_root = ctx.get_path(&sctx.location).join(&sctx.path);
-                let dst_root = ctx.dot.join(&sctx.path);
-                println!("==> updating {}", dst_root.display());
-                sync::sync(src_root, dst_root, &sctx.diffs)?;
+        if let Some(remote) = remote {
+            if verbose {
+                println!("==> pushing to {}", remote);
+            }
+            // changes are kept, they are pushed next time or with `dotto remote push`
+            if let Err(e) = remote.push(ctx, &git_dir) {
+                eprintln!("!=> changes applied and committed, push failed: {}", e);
             }
-            if let Some(git) = config.git_dir(ctx).as_ref() {
-                let mut message = String::new();
-                for sctx in &sync_ctx {
-                    for diff in &sctx.diffs {
-                        // TODO: do not join when root is file
-                        // maybe we should a small function to manage this case?
-                        message.push_str(&format!(
-                            "- {} {}\n",
-                            diff.kind(),
-                            sctx.path.join(diff.path()).display()
-                        ));
-                    }
+        }
+    }
+    Ok(())
+}
+
+const DEFAULT_SUBJECT: &str = "update {count} file(s) on {hostname}";
+const DEFAULT_BODY: &str = "{changes}";
+
+// Commit message from git subject and body templates, changes paths are relative
+// to work tree
+pub fn message(git: &Git, entries: &[PathBuf], changes: &[Diff]) -> String {
+    let changes = changes
+        .iter()
+        .map(|d| format!("- {}", d))
+        .collect::<Vec<_>>();
+    let entries = entries
+        .iter()
+        .map(|e| e.display().to_string())
+        .collect::<Vec<_>>();
+    let vars = [
+        ("hostname", git::hostname()),
+        ("count", changes.len().to_string()),
+        ("entries", entries.join(", ")),
+        ("changes", changes.join("\n")),
+    ];
+    let subject = git::render(git.subject.as_deref().unwrap_or(DEFAULT_SUBJECT), &vars);
+    let body = git::render(git.body.as_deref().unwrap_or(DEFAULT_BODY), &vars);
+    format!("{}\n\n{}\n", subject.trim(), body.trim_end())
+}
+
+fn sync_message(git: &Git, sync_ctx: &[&SyncContext]) -> String {
+    let entries = sync_ctx
+        .iter()
+        .map(|s| s.location.storage(&s.path))
+        .collect::<Vec<_>>();
+    let mut changes = Vec::new();
+    for sctx in sync_ctx {
+        for diff in &sctx.diffs {
+            // root diff of a file entry is the entry itself
+            let path = diff.full_path(sctx.location.storage(&sctx.path));
+            changes.push(Diff::new(path, diff.kind().clone()));
+        }
+    }
+    message(git, &entries, &changes)
+}
+
+fn commit(
+    ctx: &Context,
+    config: &Config,
+    git: &Git,
+    git_dir: &Path,
+    sync_ctx: &[SyncContext],
+) -> Result<()> {
+    // only entries and shared config, local config must stay out of the repository
+    let mut paths = sync_ctx
+        .iter()
+        .map(|s| s.location.storage(&s.path))
+        .collect::<Vec<_>>();
+    paths.extend(config::shared_paths(ctx));
+    if paths.is_empty() {
+        return Ok(());
+    }
+    match git.commit {
+        Granularity::None => git::stage(ctx, git_dir, &paths),
+        Granularity::Run => {
+            let all = sync_ctx.iter().collect::<Vec<_>>();
+            git::commit_paths(ctx, git_dir, &sync_message(git, &all), &paths)
+        }
+        Granularity::Entry => {
+            // glob matches are committed with the entry they come from
+            let mut groups: Vec<(Option<usize>, Vec<&SyncContext>)> = Vec::new();
+            for sctx in sync_ctx {
+                let system = ctx.get_path(&sctx.location)?.join(&sctx.path);
+                let entry = config.find_entry(ctx, &system)?.map(|(i, _)| i);
+                match groups
+                    .iter_mut()
+                    .find(|(e, _)| entry.is_some() && *e == entry)
+                {
+                    Some((_, group)) => group.push(sctx),
+                    None => groups.push((entry, vec![sctx])),
                 }
-                process::Command::new("git")
-                    .arg("--git-dir")
-                    .arg(git)
-                    .arg("--work-tree")
-                    .arg(&ctx.dot)
-                    .arg("add")
-                    .arg("-A")
-                    .status()?;
-                let mut proc = process::Command::new("git")
-                    .arg("--git-dir")
-                    .arg(git)
-                    .arg("--work-tree")
-                    .arg(&ctx.dot)
-                    .arg("commit")
-                    .arg("-F")
-                    .arg("-")
-                    .stdin(process::Stdio::piped())
-                    .spawn()?;
-                proc.stdin.as_mut().unwrap().write_all(message.as_bytes())?;
-                proc.wait()?;
             }
-        } else {
-            println!("==> cancelled");
+            for (_, group) in groups {
+                let paths = group
+                    .iter()
+                    .map(|s| s.location.storage(&s.path))
+                    .collect::<Vec<_>>();
+                git::commit_paths(ctx, git_dir, &sync_message(git, &group), &paths)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl UpdateCmd {
+    pub fn run(&self, ctx: &Context, config: &Config) -> Outcome {
+        if ctx.bare {
+            return bare::update_command(ctx, config, &self.opts, self.plan_out.is_some());
         }
-        Ok(())
+        sync_command(
+            ctx,
+            config,
+            Direction::Update,
+            &self.opts,
+            self.plan_out.as_deref(),
+            plan(ctx, config, Direction::Update, self.mode),
+            |sync_ctx| apply_update(ctx, config, sync_ctx, self.opts.is_text()),
+        )
     }
 }
@@ -0,0 +1,501 @@
+//! Minimal edits of a yaml document, comments, ordering and formatting of
+//! untouched settings are kept as is.
+//!
+//! Only block style is edited in place, flow style values (e.g `[a, b]`) are
+//! replaced entirely when they change.
+
+use crate::error::Result;
+use serde_yaml::{Mapping, Value};
+
+#[derive(Debug)]
+struct Line {
+    indent: usize,
+    content: bool,
+    // true if line is `- ...`
+    item: bool,
+    // column and name of mapping key on this line
+    key: Option<(usize, String)>,
+    // true if nothing follows `key:` on this line
+    open: bool,
+}
+
+fn parse_key(rest: &str) -> Option<(String, &str)> {
+    let (key, after) = if rest.starts_with('"') || rest.starts_with('\'') {
+        let quote = rest.chars().next().unwrap();
+        let end = rest[1..].find(quote)? + 1;
+        (rest[1..end].to_string(), &rest[end + 1..])
+    } else {
+        if rest.starts_with('#') || rest.starts_with('[') || rest.starts_with('{') {
+            return None;
+        }
+        let end = rest.find(": ").or_else(|| {
+            if rest.ends_with(':') {
+                Some(rest.len() - 1)
+            } else {
+                None
+            }
+        })?;
+        let key = &rest[..end];
+        if key.contains(" #") {
+            return None;
+        }
+        (key.trim_end().to_string(), &rest[end..])
+    };
+    let after = after.strip_prefix(':')?;
+    if !after.is_empty() && !after.starts_with(' ') {
+        return None;
+    }
+    Some((key, after.trim()))
+}
+
+fn parse_line(s: &str) -> Line {
+    let trimmed = s.trim_start();
+    let indent = s.len() - trimmed.len();
+    let marker = trimmed == "---" || trimmed.starts_with("--- ") || trimmed == "...";
+    let content = !(trimmed.is_empty() || trimmed.starts_with('#') || marker);
+    let mut col = indent;
+    let mut rest = trimmed;
+    let item = content && (rest == "-" || rest.starts_with("- "));
+    if item {
+        let after = &rest[1..];
+        let t = after.trim_start();
+        col += 1 + after.len() - t.len();
+        rest = t;
+    }
+    let mut open = false;
+    let key = if content {
+        parse_key(rest).map(|(k, after)| {
+            open = after.is_empty() || after.starts_with('#');
+            (col, k)
+        })
+    } else {
+        None
+    };
+    Line {
+        indent,
+        content,
+        item,
+        key,
+        open,
+    }
+}
+
+struct Edit {
+    start: usize,
+    end: usize,
+    lines: Vec<String>,
+}
+
+struct Document {
+    lines: Vec<String>,
+    parsed: Vec<Line>,
+    edits: Vec<Edit>,
+}
+
+fn render(value: Value, indent: usize) -> Result<Vec<String>> {
+    let s = serde_yaml::to_string(&value)?;
+    let s = s.strip_prefix("---").unwrap_or(&s).trim_start_matches('\n');
+    let pad = " ".repeat(indent);
+    Ok(s.lines().map(|l| format!("{}{}", pad, l)).collect())
+}
+
+fn render_pair(key: &Value, value: &Value, indent: usize) -> Result<Vec<String>> {
+    let mut m = Mapping::new();
+    m.insert(key.clone(), value.clone());
+    render(Value::Mapping(m), indent)
+}
+
+fn render_item(value: &Value, indent: usize) -> Result<Vec<String>> {
+    render(Value::Sequence(vec![value.clone()]), indent)
+}
+
+// Sequence items are matched by their `location` and `path` if any, by value otherwise
+fn identity(v: &Value) -> (&Value, Option<&Value>) {
+    if let Value::Mapping(m) = v {
+        if let Some(p) = m.get(&Value::String("path".to_string())) {
+            return (p, m.get(&Value::String("location".to_string())));
+        }
+    }
+    (v, None)
+}
+
+fn key_name(key: &Value) -> Option<&str> {
+    match key {
+        Value::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+impl Document {
+    fn new(text: &str) -> Document {
+        let lines = text.lines().map(String::from).collect::<Vec<_>>();
+        let parsed = lines.iter().map(|l| parse_line(l)).collect();
+        Document {
+            lines,
+            parsed,
+            edits: Vec::new(),
+        }
+    }
+
+    // End of block starting at line with given column, trailing comments are excluded
+    fn block_end(&self, line: usize, col: usize, end: usize) -> usize {
+        let mut last = line;
+        for i in line + 1..end {
+            let l = &self.parsed[i];
+            if !l.content {
+                continue;
+            }
+            // compact sequences can be at the same column as their key
+            let compact = l.item && !self.parsed[line].item;
+            if l.indent < col || (l.indent == col && !compact) {
+                break;
+            }
+            last = i;
+        }
+        last + 1
+    }
+
+    // Start of block including comments right above it
+    fn block_start(&self, line: usize, start: usize) -> usize {
+        let mut first = line;
+        while first > start && self.lines[first - 1].trim_start().starts_with('#') {
+            first -= 1;
+        }
+        first
+    }
+
+    fn entries(&self, start: usize, end: usize, col: usize) -> Vec<(String, usize, usize)> {
+        let mut entries = Vec::new();
+        for i in start..end {
+            let l = &self.parsed[i];
+            match &l.key {
+                Some((c, k)) if *c == col && (l.indent == col || i == start) => {
+                    entries.push((k.to_owned(), i, self.block_end(i, col, end)));
+                }
+                _ => {}
+            }
+        }
+        entries
+    }
+
+    fn items(&self, start: usize, end: usize) -> Option<(usize, Vec<(usize, usize)>)> {
+        let first = (start..end).find(|i| self.parsed[*i].content)?;
+        let col = self.parsed[first].indent;
+        let items = (start..end)
+            .filter(|i| self.parsed[*i].item && self.parsed[*i].indent == col)
+            .map(|i| (i, self.block_end(i, col, end)))
+            .collect();
+        Some((col, items))
+    }
+
+    fn child_col(&self, start: usize, end: usize) -> Option<usize> {
+        (start..end)
+            .find(|i| self.parsed[*i].content)
+            .map(|i| self.parsed[i].indent)
+    }
+
+    fn replace(&mut self, start: usize, end: usize, lines: Vec<String>) {
+        self.edits.push(Edit { start, end, lines });
+    }
+
+    fn edit_mapping(
+        &mut self,
+        start: usize,
+        end: usize,
+        col: usize,
+        old: &Mapping,
+        new: &Mapping,
+    ) -> Result<()> {
+        let entries = self.entries(start, end, col);
+        let find = |key: &Value| {
+            key_name(key).and_then(|k| entries.iter().find(|(name, _, _)| name == k).cloned())
+        };
+        let insert_at = entries.last().map(|(_, _, e)| *e).unwrap_or(end);
+        for (key, value) in new {
+            let prev = old.get(key).unwrap_or(&Value::Null);
+            if prev == value {
+                continue;
+            }
+            match find(key) {
+                Some((_, s, e)) => self.edit_value(s, e, col, key, prev, value)?,
+                None if value.is_null() => {}
+                None => {
+                    let lines = render_pair(key, value, col)?;
+                    self.replace(insert_at, insert_at, lines);
+                }
+            }
+        }
+        for (key, _) in old {
+            if new.contains_key(key) {
+                continue;
+            }
+            if let Some((_, s, e)) = find(key) {
+                let s = self.block_start(s, start);
+                self.replace(s, e, Vec::new());
+            }
+        }
+        Ok(())
+    }
+
+    fn edit_value(
+        &mut self,
+        start: usize,
+        end: usize,
+        col: usize,
+        key: &Value,
+        old: &Value,
+        new: &Value,
+    ) -> Result<()> {
+        let line = &self.parsed[start];
+        // key on a `- ` line can only be edited through its item
+        let inline = line.indent != col;
+        if line.open && !inline {
+            if let Some(child) = self.child_col(start + 1, end) {
+                match (old, new) {
+                    (Value::Mapping(o), Value::Mapping(n)) if child > col => {
+                        return self.edit_mapping(start + 1, end, child, o, n);
+                    }
+                    // an emptied sequence is rendered as `[]`
+                    (Value::Sequence(o), Value::Sequence(n))
+                        if !n.is_empty() && self.edit_sequence(start + 1, end, o, n)? =>
+                    {
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let lines = render_pair(key, new, col)?;
+        if inline {
+            // keep `- ` prefix of item
+            let prefix = self.lines[start][..col].to_string();
+            let mut lines = lines;
+            if let Some(first) = lines.first_mut() {
+                *first = format!("{}{}", prefix, &first[col.min(first.len())..]);
+            }
+            self.replace(start, end, lines);
+        } else {
+            self.replace(start, end, lines);
+        }
+        Ok(())
+    }
+
+    // Returns false if sequence cannot be edited in place
+    fn edit_sequence(
+        &mut self,
+        start: usize,
+        end: usize,
+        old: &[Value],
+        new: &[Value],
+    ) -> Result<bool> {
+        let (col, items) = match self.items(start, end) {
+            Some(i) => i,
+            None => return Ok(false),
+        };
+        if items.len() != old.len() {
+            return Ok(false);
+        }
+        for (prev, (s, e)) in old.iter().zip(items.iter()) {
+            match new.iter().find(|n| identity(n) == identity(prev)) {
+                None => {
+                    let s = self.block_start(*s, start);
+                    self.replace(s, *e, Vec::new());
+                }
+                Some(n) if n != prev => self.edit_item(*s, *e, col, prev, n)?,
+                _ => {}
+            }
+        }
+        let insert_at = items.last().map(|(_, e)| *e).unwrap_or(end);
+        for n in new {
+            if old.iter().any(|o| identity(o) == identity(n)) {
+                continue;
+            }
+            let lines = render_item(n, col)?;
+            self.replace(insert_at, insert_at, lines);
+        }
+        Ok(true)
+    }
+
+    fn edit_item(
+        &mut self,
+        start: usize,
+        end: usize,
+        col: usize,
+        old: &Value,
+        new: &Value,
+    ) -> Result<()> {
+        if let (Value::Mapping(o), Value::Mapping(n), Some((kcol, k))) =
+            (old, new, self.parsed[start].key.clone())
+        {
+            let k = Value::String(k);
+            if o.get(&k).is_some() && o.get(&k) == n.get(&k) {
+                return self.edit_mapping(start, end, kcol, o, n);
+            }
+        }
+        let lines = render_item(new, col)?;
+        self.replace(start, end, lines);
+        Ok(())
+    }
+
+    fn finish(mut self, trailing_newline: bool) -> String {
+        let mut edits = self.edits.into_iter().enumerate().collect::<Vec<_>>();
+        // apply from the end, insertions at the same line keep their order
+        edits.sort_by(|(ia, a), (ib, b)| (b.start, ib).cmp(&(a.start, ia)));
+        for (_, edit) in edits {
+            self.lines.splice(edit.start..edit.end, edit.lines);
+        }
+        let mut out = self.lines.join("\n");
+        if trailing_newline {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// Apply differences between old and new to text, old must be the value of text
+pub fn update(text: &str, old: &Value, new: &Value) -> Result<String> {
+    let mut doc = Document::new(text);
+    match (old, new) {
+        (Value::Mapping(o), Value::Mapping(n)) if doc.parsed.iter().any(|l| l.content) => {
+            let end = doc.lines.len();
+            let col = doc.child_col(0, end).unwrap_or(0);
+            doc.edit_mapping(0, end, col, o, n)?;
+            Ok(doc.finish(text.ends_with('\n') || text.is_empty()))
+        }
+        _ => Ok(serde_yaml::to_string(new)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Apply change to the value of text and return updated text
+    fn edit<F: FnOnce(&mut Mapping)>(text: &str, change: F) -> String {
+        let old: Value = serde_yaml::from_str(text).unwrap();
+        let mut new = old.clone();
+        change(new.as_mapping_mut().unwrap());
+        let out = update(text, &old, &new).unwrap();
+        assert_eq!(serde_yaml::from_str::<Value>(&out).unwrap(), new);
+        out
+    }
+
+    fn key(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    fn files(m: &mut Mapping) -> &mut Vec<Value> {
+        m.get_mut(&key("files"))
+            .and_then(Value::as_sequence_mut)
+            .unwrap()
+    }
+
+    fn entry(path: &str, location: &str) -> Value {
+        let mut m = Mapping::new();
+        m.insert(key("path"), key(path));
+        m.insert(key("location"), key(location));
+        Value::Mapping(m)
+    }
+
+    const TEXT: &str = "\
+# shared dotfiles
+
+trash: true  # keep deleted files
+
+files:
+  # editor
+  - path: .vimrc
+    location: home
+  - path: nvim
+    location: config
+    exclude:
+      - plugins  # downloaded
+      - '*.log'
+exclude: [.git, target]
+";
+
+    #[test]
+    fn unchanged() {
+        assert_eq!(edit(TEXT, |_| {}), TEXT);
+    }
+
+    #[test]
+    fn comments_and_order() {
+        let out = edit(TEXT, |m| {
+            m.insert(key("trash"), Value::Bool(false));
+            m.insert(key("max_deletions"), Value::Number(10.into()));
+        });
+        assert_eq!(
+            out,
+            TEXT.replace("trash: true  # keep deleted files", "trash: false")
+                + "max_deletions: 10\n"
+        );
+    }
+
+    #[test]
+    fn remove_setting() {
+        let out = edit(TEXT, |m| {
+            m.remove(&key("trash"));
+        });
+        assert_eq!(out, TEXT.replace("trash: true  # keep deleted files\n", ""));
+    }
+
+    #[test]
+    fn add_and_remove_files() {
+        let out = edit(TEXT, |m| {
+            files(m).remove(0);
+            files(m).push(entry("bin", "home"));
+        });
+        let expected = TEXT
+            .replace("  # editor\n  - path: .vimrc\n    location: home\n", "")
+            .replace(
+                "      - '*.log'\n",
+                "      - '*.log'\n  - path: bin\n    location: home\n",
+            );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn add_and_remove_excludes() {
+        let out = edit(TEXT, |m| {
+            let nvim = files(m)[1].as_mapping_mut().unwrap();
+            let exclude = nvim
+                .get_mut(&key("exclude"))
+                .and_then(Value::as_sequence_mut)
+                .unwrap();
+            exclude.remove(1);
+            exclude.push(key("cache"));
+        });
+        let expected = TEXT.replace("      - '*.log'\n", "      - cache\n");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn flow_list_is_replaced() {
+        let out = edit(TEXT, |m| {
+            let exclude = m
+                .get_mut(&key("exclude"))
+                .and_then(Value::as_sequence_mut)
+                .unwrap();
+            exclude.push(key("*.bak"));
+        });
+        let expected = TEXT.replace(
+            "exclude: [.git, target]\n",
+            "exclude:\n  - \".git\"\n  - target\n  - \"*.bak\"\n",
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn emptied_list() {
+        let out = edit(TEXT, |m| {
+            let nvim = files(m)[1].as_mapping_mut().unwrap();
+            nvim.insert(key("exclude"), Value::Sequence(Vec::new()));
+        });
+        let expected = TEXT.replace(
+            "    exclude:\n      - plugins  # downloaded\n      - '*.log'\n",
+            "    exclude: []\n",
+        );
+        assert_eq!(out, expected);
+    }
+}
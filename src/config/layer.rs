@@ -0,0 +1,310 @@
+//! Configuration layers: shared config, `config.d` includes and machine local overrides
+
+use super::{Config, Context, File, Location};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const INCLUDE_DIR: &str = "config.d";
+pub const LOCAL_CONFIG: &str = "config.local.yml";
+
+#[derive(Debug)]
+pub struct Layer {
+    pub path: PathBuf,
+    pub(super) config: Config,
+    // content as read from disk
+    pub(super) text: String,
+}
+
+// Index of the layer that defines each setting
+#[derive(Debug, Default)]
+pub struct Origins {
+    // last layer defining file entry, entries are keyed by location and path
+    files: HashMap<(Location, PathBuf), usize>,
+    // first layer defining exclude for file entry
+    excludes: HashMap<((Location, PathBuf), String), usize>,
+    // first layer defining global exclude
+    global_excludes: HashMap<String, usize>,
+    // last layer defining top level settings (git, dot, escalate, max_deletions, trash, locations.<name>)
+    settings: HashMap<String, usize>,
+    // key entries had in layers, for entries moved since config was opened
+    moved: HashMap<(Location, PathBuf), (Location, PathBuf)>,
+}
+
+// Machine wide user config, outside of dot directory
+pub fn user_config(ctx: &Context) -> PathBuf {
+    ctx.xdg_config.join("dotto").join("config.yml")
+}
+
+// Machine local overrides, next to main config
+pub fn local_config(ctx: &Context) -> PathBuf {
+    let dir = ctx.dot_config.parent().map(Path::to_owned);
+    dir.unwrap_or_default().join(LOCAL_CONFIG)
+}
+
+// Returns all layers paths in merge order, main config is always first
+pub fn layer_paths(ctx: &Context) -> Vec<PathBuf> {
+    let base = ctx.dot_config.to_owned();
+    let dir = base.parent().map(Path::to_owned).unwrap_or_default();
+    let mut paths = vec![base];
+    let mut includes = glob::glob(&dir.join(INCLUDE_DIR).join("*.yml").display().to_string())
+        .map(|g| g.filter_map(|p| p.ok()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    includes.sort();
+    paths.extend(includes);
+    for local in &[local_config(ctx), user_config(ctx)] {
+        if local.is_file() {
+            paths.push(local.to_owned());
+        }
+    }
+    paths
+}
+
+// Layers committed with dot directory, relative to work tree. Local and user
+// configs are machine specific and never staged
+pub fn shared_paths(ctx: &Context) -> Vec<PathBuf> {
+    let machine = [local_config(ctx), user_config(ctx)];
+    layer_paths(ctx)
+        .into_iter()
+        .filter(|p| p.exists() && !machine.contains(p))
+        .filter_map(|p| p.strip_prefix(ctx.work_tree()).ok().map(Path::to_owned))
+        .collect()
+}
+
+// Pick value for layer idx: value is written only by the last layer defining it,
+// other layers keep what they had
+fn pick<T: Clone>(
+    origin: Option<usize>,
+    idx: usize,
+    merged: &Option<T>,
+    orig: &Option<T>,
+) -> Option<T> {
+    if origin.unwrap_or(0) == idx {
+        merged.clone()
+    } else {
+        orig.clone()
+    }
+}
+
+// Key of file entry in origins
+fn key(file: &File) -> (Location, PathBuf) {
+    (file.location.clone(), file.path.to_owned())
+}
+
+fn same(a: &File, b: &File) -> bool {
+    a.location == b.location && a.path == b.path
+}
+
+impl Config {
+    // Merge layer on top of self, files are merged by location and path
+    pub(super) fn merge(&mut self, layer: Config, text: String, path: PathBuf) {
+        let idx = self.layers.len();
+        for file in &layer.files {
+            self.origins.files.insert(key(file), idx);
+            for exclude in &file.exclude {
+                self.origins
+                    .excludes
+                    .entry((key(file), exclude.to_owned()))
+                    .or_insert(idx);
+            }
+            match self.files.iter_mut().find(|f| same(f, file)) {
+                Some(f) => {
+                    let mut exclude = std::mem::take(&mut f.exclude);
+                    for e in &file.exclude {
+                        if !exclude.contains(e) {
+                            exclude.push(e.to_owned());
+                        }
+                    }
+                    *f = file.clone();
+                    f.exclude = exclude;
+                }
+                None => self.files.push(file.clone()),
+            }
+        }
+        for exclude in &layer.exclude {
+            self.origins
+                .global_excludes
+                .entry(exclude.to_owned())
+                .or_insert(idx);
+            if !self.exclude.contains(exclude) {
+                self.exclude.push(exclude.to_owned());
+            }
+        }
+        if layer.git.is_some() {
+            self.git = layer.git.clone();
+            self.origins.settings.insert("git".to_string(), idx);
+        }
+        if layer.dot.is_some() {
+            self.dot = layer.dot.clone();
+            self.origins.settings.insert("dot".to_string(), idx);
+        }
+        if layer.escalate.is_some() {
+            self.escalate = layer.escalate.clone();
+            self.origins.settings.insert("escalate".to_string(), idx);
+        }
+        if layer.max_deletions.is_some() {
+            self.max_deletions = layer.max_deletions;
+            self.origins
+                .settings
+                .insert("max_deletions".to_string(), idx);
+        }
+        if layer.trash.is_some() {
+            self.trash = layer.trash;
+            self.origins.settings.insert("trash".to_string(), idx);
+        }
+        for (name, base) in &layer.locations {
+            self.locations.insert(name.to_owned(), base.to_owned());
+            self.origins
+                .settings
+                .insert(format!("locations.{}", name), idx);
+        }
+        self.layers.push(Layer {
+            path,
+            config: layer,
+            text,
+        });
+    }
+
+    // Returns content of layer idx from merged config
+    pub(super) fn split(&self, idx: usize) -> Config {
+        let orig = &self.layers[idx].config;
+        let mut config = Config::default();
+        for file in &self.files {
+            let last = self.origins.files.get(&key(file)).cloned().unwrap_or(0);
+            let exclude = file
+                .exclude
+                .iter()
+                .filter(|e| {
+                    let key = (key(file), e.to_string());
+                    self.origins.excludes.get(&key).cloned().unwrap_or(last) == idx
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let mut file = if last == idx {
+                file.clone()
+            } else {
+                let (location, path) = self
+                    .origins
+                    .moved
+                    .get(&key(file))
+                    .cloned()
+                    .unwrap_or_else(|| key(file));
+                match orig
+                    .files
+                    .iter()
+                    .find(|f| f.location == location && f.path == path)
+                {
+                    Some(f) => File {
+                        location: file.location.clone(),
+                        path: file.path.to_owned(),
+                        ..f.clone()
+                    },
+                    None => continue,
+                }
+            };
+            file.exclude = exclude;
+            config.files.push(file);
+        }
+        config.exclude = self
+            .exclude
+            .iter()
+            .filter(|e| self.origins.global_excludes.get(*e).cloned().unwrap_or(0) == idx)
+            .cloned()
+            .collect();
+        let setting = |key: &str| self.origins.settings.get(key).cloned();
+        config.git = pick(setting("git"), idx, &self.git, &orig.git);
+        // dot directory is machine specific, it is never written into main config
+        config.dot = match setting("dot") {
+            Some(_) => pick(setting("dot"), idx, &self.dot, &orig.dot),
+            None => orig.dot.clone(),
+        };
+        config.escalate = pick(setting("escalate"), idx, &self.escalate, &orig.escalate);
+        config.max_deletions = pick(
+            setting("max_deletions"),
+            idx,
+            &self.max_deletions,
+            &orig.max_deletions,
+        );
+        config.trash = pick(setting("trash"), idx, &self.trash, &orig.trash);
+        for (name, base) in &self.locations {
+            let key = format!("locations.{}", name);
+            let value = pick(
+                setting(&key),
+                idx,
+                &Some(base.to_owned()),
+                &orig.locations.get(name).cloned(),
+            );
+            if let Some(value) = value {
+                config.locations.insert(name.to_owned(), value);
+            }
+        }
+        config
+    }
+
+    // Entry now has a new location and path, it stays in the layers defining it
+    pub(super) fn rekey_file(&mut self, old: (Location, PathBuf), new: (Location, PathBuf)) {
+        let origins = &mut self.origins;
+        if let Some(idx) = origins.files.remove(&old) {
+            origins.files.insert(new.clone(), idx);
+        }
+        let excludes = origins
+            .excludes
+            .keys()
+            .filter(|(k, _)| *k == old)
+            .cloned()
+            .collect::<Vec<_>>();
+        for (k, exclude) in excludes {
+            let idx = origins.excludes.remove(&(k, exclude.to_owned())).unwrap();
+            origins.excludes.insert((new.clone(), exclude), idx);
+        }
+        let first = origins.moved.remove(&old).unwrap_or(old);
+        origins.moved.insert(new, first);
+    }
+
+    // Exclude of entry has been renamed, it stays in the layer defining it
+    pub(super) fn rekey_exclude(&mut self, file: &File, old: &str, new: &str) {
+        let excludes = &mut self.origins.excludes;
+        if let Some(idx) = excludes.remove(&(key(file), old.to_owned())) {
+            excludes.insert((key(file), new.to_owned()), idx);
+        }
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    // Layers inside old directory are now in new one
+    pub fn rebase_layers(&mut self, old: &Path, new: &Path) {
+        for layer in &mut self.layers {
+            if let Ok(rest) = layer.path.strip_prefix(old) {
+                layer.path = new.join(rest);
+            }
+        }
+    }
+
+    fn layer_path(&self, idx: Option<usize>) -> Option<&Path> {
+        self.layers.get(idx.unwrap_or(0)).map(|l| l.path.as_path())
+    }
+
+    // Returns layer path defining file entry
+    pub fn file_origin(&self, file: &File) -> Option<&Path> {
+        self.layer_path(self.origins.files.get(&key(file)).cloned())
+    }
+
+    // Returns layer path defining exclude for file entry
+    pub fn exclude_origin(&self, file: &File, exclude: &str) -> Option<&Path> {
+        match self.origins.excludes.get(&(key(file), exclude.to_owned())) {
+            Some(idx) => self.layer_path(Some(*idx)),
+            None => self.file_origin(file),
+        }
+    }
+
+    // Returns layer path defining global exclude
+    pub fn global_exclude_origin(&self, exclude: &str) -> Option<&Path> {
+        self.layer_path(self.origins.global_excludes.get(exclude).cloned())
+    }
+
+    // Returns layer path defining top level setting (git, dot, escalate, max_deletions, trash, locations.<name>)
+    pub fn setting_origin(&self, key: &str) -> Option<&Path> {
+        self.layer_path(self.origins.settings.get(key).cloned())
+    }
+}
@@ -0,0 +1,1042 @@
+mod document;
+mod layer;
+
+pub use self::layer::*;
+
+use crate::error::{DotError, Result};
+use crate::sync::SyncSettings;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+fn bool_is_false(b: &bool) -> bool {
+    !*b
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Location {
+    Home,
+    Config,
+    Data,
+    State,
+    Cache,
+    Bin,
+    Absolute,
+    // user defined location, declared in config `locations`
+    Named(String),
+}
+
+impl Location {
+    fn builtin(name: &str) -> Option<Location> {
+        match name {
+            "home" => Some(Location::Home),
+            "config" => Some(Location::Config),
+            "data" => Some(Location::Data),
+            "state" => Some(Location::State),
+            "cache" => Some(Location::Cache),
+            "bin" => Some(Location::Bin),
+            "absolute" => Some(Location::Absolute),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Location::Home => "home",
+            Location::Config => "config",
+            Location::Data => "data",
+            Location::State => "state",
+            Location::Cache => "cache",
+            Location::Bin => "bin",
+            Location::Absolute => "absolute",
+            Location::Named(n) => n,
+        }
+    }
+
+    // Entry path in dot directory, relative to it. Home, config and absolute entries
+    // are at the top as they have always been, newer locations are kept apart under
+    // `@<name>` so their paths do not collide with existing ones.
+    pub fn storage<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        match self {
+            Location::Home | Location::Config | Location::Absolute => path.as_ref().to_owned(),
+            _ => Path::new(&format!("@{}", self.name())).join(path),
+        }
+    }
+}
+
+impl From<&str> for Location {
+    fn from(name: &str) -> Location {
+        Location::builtin(name).unwrap_or_else(|| Location::Named(name.to_string()))
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Serialize for Location {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Location {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Location, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Location::from(name.as_str()))
+    }
+}
+
+// Expand `~`, `$VAR` and `${VAR}` in path
+pub fn expand_path(path: &str) -> Result<PathBuf> {
+    let mut out = String::new();
+    let mut chars = path.chars().peekable();
+    if path == "~" || path.starts_with("~/") {
+        out.push_str(&env::var("HOME").map_err(|e| DotError::wrap("HOME", e))?);
+        chars.next();
+    }
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        let mut name = String::new();
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            for c in chars.by_ref() {
+                if c == '}' {
+                    break;
+                }
+                name.push(c);
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+        }
+        if name.is_empty() {
+            out.push('$');
+            continue;
+        }
+        out.push_str(&env::var(&name).map_err(|e| DotError::wrap(&name, e))?);
+    }
+    Ok(PathBuf::from(out))
+}
+
+// How deletions are handled when syncing an entry
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncMode {
+    // destination is made identical to source
+    #[default]
+    Mirror,
+    // nothing is ever deleted, deletions are listed as skipped
+    Additive,
+    // each deletion has to be confirmed
+    PromptDeletes,
+}
+
+impl SyncMode {
+    fn is_mirror(&self) -> bool {
+        *self == SyncMode::Mirror
+    }
+}
+
+impl FromStr for SyncMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<SyncMode, String> {
+        match s {
+            "mirror" => Ok(SyncMode::Mirror),
+            "additive" => Ok(SyncMode::Additive),
+            "prompt-deletes" => Ok(SyncMode::PromptDeletes),
+            _ => Err(format!(
+                "unknown mode {}, expected mirror, additive or prompt-deletes",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncMode::Mirror => write!(f, "mirror"),
+            SyncMode::Additive => write!(f, "additive"),
+            SyncMode::PromptDeletes => write!(f, "prompt-deletes"),
+        }
+    }
+}
+
+// TODO: allow rename
+// TODO: fix recursive not taken into account
+// TODO: add exclude to hide secret files
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct File {
+    pub path: PathBuf,
+    pub location: Location,
+    #[serde(skip_serializing_if = "bool_is_false", default)]
+    pub recursive: bool,
+    #[serde(skip_serializing_if = "bool_is_false", default)]
+    pub symbolic: bool,
+    #[serde(skip_serializing_if = "SyncMode::is_mirror", default)]
+    pub mode: SyncMode,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub exclude: Vec<String>,
+    // if not empty, only matching paths are synced (gitignore style, `!` negates)
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub include: Vec<String>,
+}
+
+fn is_pattern(s: &str) -> bool {
+    s.contains('*') || s.contains('?') || s.contains('[')
+}
+
+impl File {
+    // A glob entry has a pattern as path, it is expanded on source side when syncing
+    pub fn is_glob(&self) -> bool {
+        self.path.to_str().map(is_pattern).unwrap_or(false)
+    }
+
+    // Returns the part of path before first pattern component
+    pub fn glob_base(&self) -> PathBuf {
+        self.path
+            .components()
+            .take_while(|c| {
+                c.as_os_str()
+                    .to_str()
+                    .map(|s| !is_pattern(s))
+                    .unwrap_or(true)
+            })
+            .collect()
+    }
+
+    pub fn settings(&self) -> Result<SyncSettings> {
+        SyncSettings::new(0, self.recursive, self.exclude.as_slice())?.include(&self.include)
+    }
+
+    // Expand glob entry under root, returned paths are relative to root
+    pub fn expand<P: AsRef<Path>>(&self, root: P, settings: &SyncSettings) -> Result<Vec<PathBuf>> {
+        let root = root.as_ref();
+        let pattern = format!(
+            "{}/{}",
+            glob::Pattern::escape(&root.display().to_string()),
+            self.path.display()
+        );
+        let base = self.glob_base();
+        let mut paths = Vec::new();
+        for entry in glob::glob(&pattern).map_err(|e| DotError::wrap(&pattern, e))? {
+            let entry = entry?;
+            let rel = entry.strip_prefix(root)?.to_owned();
+            let from_base = rel.strip_prefix(&base).unwrap_or(&rel);
+            if settings.is_excluded(from_base) {
+                continue;
+            }
+            // a matched directory is kept, its content is filtered when syncing
+            if !entry.is_dir() && !settings.is_included(from_base) {
+                continue;
+            }
+            paths.push(rel);
+        }
+        Ok(paths)
+    }
+}
+
+// Result of Config::remove_path
+#[derive(Debug)]
+pub enum Removed {
+    Entry(File),
+    Excluded { entry: File, path: PathBuf },
+}
+
+// How many commits update makes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    // one commit for the whole run
+    #[default]
+    Run,
+    // one commit per config entry
+    Entry,
+    // changes are only staged
+    None,
+}
+
+impl Granularity {
+    fn is_run(&self) -> bool {
+        *self == Granularity::Run
+    }
+}
+
+impl fmt::Display for Granularity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Granularity::Run => write!(f, "run"),
+            Granularity::Entry => write!(f, "entry"),
+            Granularity::None => write!(f, "none"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Git {
+    pub path: PathBuf,
+    pub location: Location,
+    // commit message templates, variables: {hostname}, {count}, {entries}, {changes}
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub body: Option<String>,
+    #[serde(skip_serializing_if = "Granularity::is_run", default)]
+    pub commit: Granularity,
+    // remote name or url, branch defaults to current one
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub remote: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub branch: Option<String>,
+    // push after update commits, pull before install
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub push: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub pull: bool,
+    // home is the work tree, nothing is copied to dot directory (yadm style)
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub bare: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Config {
+    #[serde(default)]
+    pub files: Vec<File>,
+    // excludes applied to every entry (e.g *.swp, __pycache__)
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub exclude: Vec<String>,
+    git: Option<Git>,
+    pub dot: Option<PathBuf>,
+    // command used to gain privileges (e.g sudo, doas, pkexec)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub escalate: Option<String>,
+    // maximum deletions in a single install or update, opt-in: no limit if unset or 0
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max_deletions: Option<usize>,
+    // move deleted files to trash instead of unlinking them
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub trash: Option<bool>,
+    // user defined locations, values can contains env variables (e.g work: ${WORK_DIR})
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub locations: BTreeMap<String, String>,
+    // files this config has been merged from, empty if config is not opened yet
+    #[serde(skip)]
+    layers: Vec<Layer>,
+    #[serde(skip)]
+    origins: Origins,
+}
+
+impl Config {
+    // Read a single config file, without any layering
+    fn read<P: AsRef<Path>>(path: P) -> Result<(Config, String)> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        let mut config: Config = serde_yaml::from_str(&text)
+            .map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        // absolute entries are relative to `/` (or sandbox root), like other locations
+        for file in &mut config.files {
+            if file.location == Location::Absolute {
+                if let Ok(p) = file.path.strip_prefix("/") {
+                    file.path = p.to_owned();
+                }
+            }
+        }
+        Ok((config, text))
+    }
+
+    // Returns `dot` setting, read before opening config to locate dot directory.
+    // Local and user configs win over main config, relative paths are relative to their config.
+    pub fn read_dot(ctx: &Context) -> Result<Option<PathBuf>> {
+        let mut dot = None;
+        for path in &[
+            ctx.dot_config.to_owned(),
+            local_config(ctx),
+            user_config(ctx),
+        ] {
+            if !path.is_file() {
+                continue;
+            }
+            let (config, _) = Config::read(path)?;
+            let value = match config.dot {
+                Some(d) => d,
+                None => continue,
+            };
+            let value = match value.strip_prefix("~") {
+                Ok(rest) => ctx.home.join(rest),
+                Err(_) => expand_path(&value.display().to_string())?,
+            };
+            let dir = path.parent().unwrap_or_else(|| Path::new("/"));
+            dot = Some(dir.join(value));
+        }
+        Ok(dot)
+    }
+
+    // Record dot directory in user config, so it is found without $DOT_PATH
+    pub fn save_user_dot(ctx: &Context, dot: &Path) -> Result<()> {
+        let path = user_config(ctx);
+        let dot = serde_yaml::Value::String(dot.display().to_string());
+        if !path.is_file() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut config = serde_yaml::Mapping::new();
+            config.insert("dot".into(), dot);
+            fs::write(&path, serde_yaml::to_string(&config)?)?;
+            return Ok(());
+        }
+        let text = fs::read_to_string(&path)?;
+        let old: serde_yaml::Value = serde_yaml::from_str(&text)
+            .map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        let mut new = match &old {
+            serde_yaml::Value::Mapping(m) => m.clone(),
+            _ => serde_yaml::Mapping::new(),
+        };
+        new.insert("dot".into(), dot);
+        let text = document::update(&text, &old, &serde_yaml::Value::Mapping(new))?;
+        fs::write(&path, text)?;
+        Ok(())
+    }
+
+    // Open config and merge all its layers (includes and local overrides).
+    // Entries must use a builtin location or one declared in `locations`.
+    pub fn open(ctx: &Context) -> Result<Config> {
+        let mut config = Config::default();
+        for path in layer_paths(ctx) {
+            let (layer, text) = Config::read(&path)?;
+            config.merge(layer, text, path);
+        }
+        let locations = config.files.iter().map(|f| &f.location);
+        for loc in locations.chain(config.git.iter().map(|g| &g.location)) {
+            if let Location::Named(name) = loc {
+                if !config.locations.contains_key(name) {
+                    return Err(DotError::UnknownLocation(name.to_owned()).into());
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    // Write config back, every setting is written into the layer it comes from.
+    // Layers are edited in place to keep comments and formatting, unchanged layers are not written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if self.layers.is_empty() {
+            let mut file = fs::File::create(path)?;
+            return serde_yaml::to_writer(&mut file, self).map_err(failure::Error::from);
+        }
+        for (idx, layer) in self.layers.iter().enumerate() {
+            // compare with the document as written, not as read into config
+            let old = match serde_yaml::from_str(&layer.text)? {
+                serde_yaml::Value::Null => serde_yaml::Value::Mapping(Default::default()),
+                old => old,
+            };
+            let new = serde_yaml::to_value(self.written(idx)?)?;
+            if old == new {
+                continue;
+            }
+            let text = document::update(&layer.text, &old, &new)?;
+            fs::write(&layer.path, text)?;
+        }
+        Ok(())
+    }
+
+    // Layer idx as it is written: absolute paths get back their leading `/`,
+    // unless the layer already has them without it
+    fn written(&self, idx: usize) -> Result<Config> {
+        let mut config = self.split(idx);
+        let raw: Config = serde_yaml::from_str(&self.layers[idx].text)?;
+        for file in &mut config.files {
+            let unrooted = raw
+                .files
+                .iter()
+                .any(|f| f.location == Location::Absolute && f.path == file.path);
+            if file.location == Location::Absolute && !unrooted {
+                file.path = Path::new("/").join(&file.path);
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn add_file<P: AsRef<Path>>(
+        &mut self,
+        ctx: &Context,
+        path: P,
+        recursive: bool,
+    ) -> Result<()> {
+        let (path, loc) = ctx.abs_clean_path(path)?;
+        // an empty path would map to the whole dot directory
+        if path.as_os_str().is_empty() {
+            return Err(DotError::LocationRoot(loc.to_string()).into());
+        }
+        let target = ctx.get_path(&loc)?.join(&path);
+        let stored = loc.storage(&path);
+        for file in &self.files {
+            if file.location == loc && file.path == path {
+                return Err(DotError::AlreadyManaged(target.display().to_string()).into());
+            }
+            // home, config and absolute entries share the top of dot directory
+            let other = file.location.storage(&file.path);
+            if file.location != loc
+                && !file.is_glob()
+                && (other.starts_with(&stored) || stored.starts_with(&other))
+            {
+                return Err(DotError::Overlaps(
+                    target.display().to_string(),
+                    ctx.dot.join(other).display().to_string(),
+                )
+                .into());
+            }
+            // the same system path can be reached through several locations
+            let root = match ctx.get_path(&file.location) {
+                Ok(base) => base.join(file.glob_base()),
+                Err(_) => continue,
+            };
+            let absorbed = file.location == loc && file.path.starts_with(&path);
+            if (!file.is_glob() && target.starts_with(&root))
+                || (root.starts_with(&target) && !absorbed)
+            {
+                return Err(DotError::Overlaps(
+                    target.display().to_string(),
+                    root.display().to_string(),
+                )
+                .into());
+            }
+        }
+        // entries inside added directory are replaced by it, they are stored under it
+        self.files
+            .retain(|f| !(f.location == loc && f.path.starts_with(&path)));
+        self.files.push(File {
+            path,
+            recursive,
+            location: loc,
+            symbolic: false,
+            mode: SyncMode::Mirror,
+            exclude: Vec::new(),
+            include: Vec::new(),
+        });
+        Ok(())
+    }
+
+    // Find entry containing system path, returns entry index and path relative to entry root
+    pub fn find_entry<P: AsRef<Path>>(
+        &self,
+        ctx: &Context,
+        path: P,
+    ) -> Result<Option<(usize, PathBuf)>> {
+        let (path, loc) = ctx.abs_clean_path(path)?;
+        let path = ctx.get_path(&loc)?.join(path);
+        Ok(self.entry_at(ctx, &path))
+    }
+
+    // Path of a managed system path in git work tree, with the entry containing it
+    pub fn repo_path<P: AsRef<Path>>(&self, ctx: &Context, path: P) -> Result<(&File, PathBuf)> {
+        let path = path.as_ref();
+        let (idx, rel) = self
+            .find_entry(ctx, path)?
+            .ok_or_else(|| DotError::NoMatch(path.display().to_string()))?;
+        let file = &self.files[idx];
+        if ctx.bare {
+            let (rel, loc) = ctx.abs_clean_path(path)?;
+            return Ok((file, ctx.home_path(&ctx.get_path(&loc)?.join(rel))?));
+        }
+        let base = file.location.storage(file.glob_base());
+        if rel.as_os_str().is_empty() {
+            return Ok((file, base));
+        }
+        Ok((file, base.join(rel)))
+    }
+
+    // Find most specific entry containing absolute path
+    fn entry_at(&self, ctx: &Context, path: &Path) -> Option<(usize, PathBuf)> {
+        self.files
+            .iter()
+            .enumerate()
+            .filter_map(|(i, file)| {
+                // compare absolute paths, the same file can be reached through several locations
+                let file_root = ctx.get_path(&file.location).ok()?.join(file.glob_base());
+                let rel = path.strip_prefix(&file_root).ok()?;
+                Some((i, rel.to_owned(), file_root.components().count()))
+            })
+            .max_by_key(|(_, _, depth)| *depth)
+            .map(|(i, rel, _)| (i, rel))
+    }
+
+    // Stop managing path, a sub path of a directory entry is excluded instead
+    pub fn remove_path<P: AsRef<Path>>(&mut self, ctx: &Context, path: P) -> Result<Removed> {
+        let path = path.as_ref();
+        let (idx, rel) = match self.find_entry(ctx, path)? {
+            Some(e) => e,
+            None => return Err(DotError::NoMatch(path.display().to_string()).into()),
+        };
+        if rel.as_os_str().is_empty() {
+            return Ok(Removed::Entry(self.files.remove(idx)));
+        }
+        let file = &mut self.files[idx];
+        let exclude = rel.display().to_string();
+        if !file.exclude.contains(&exclude) {
+            file.exclude.push(exclude);
+        }
+        Ok(Removed::Excluded {
+            entry: file.clone(),
+            path: rel,
+        })
+    }
+
+    // Move managed path, entry root or a path inside the same entry.
+    // Returns old and new paths relative to dot directory.
+    pub fn move_path<A, B>(&mut self, ctx: &Context, old: A, new: B) -> Result<(PathBuf, PathBuf)>
+    where
+        A: AsRef<Path>,
+        B: AsRef<Path>,
+    {
+        let (old, new) = (old.as_ref(), new.as_ref());
+        let (idx, rel) = match self.find_entry(ctx, old)? {
+            Some(e) => e,
+            None => return Err(DotError::NoMatch(old.display().to_string()).into()),
+        };
+        if rel.as_os_str().is_empty() {
+            let (path, loc) = ctx.abs_clean_path(new)?;
+            if path.as_os_str().is_empty() {
+                return Err(DotError::LocationRoot(loc.to_string()).into());
+            }
+            if self
+                .files
+                .iter()
+                .any(|f| f.location == loc && f.path == path)
+            {
+                let target = ctx.get_path(&loc)?.join(&path);
+                return Err(DotError::AlreadyManaged(target.display().to_string()).into());
+            }
+            let file = &mut self.files[idx];
+            let old = (file.location.clone(), file.path.to_owned());
+            file.path = path;
+            file.location = loc;
+            let new = (file.location.clone(), file.path.to_owned());
+            let moved = (old.0.storage(&old.1), new.0.storage(&new.1));
+            self.rekey_file(old, new);
+            return Ok(moved);
+        }
+        let new_rel = match self.find_entry(ctx, new)? {
+            Some((i, r)) if i == idx && !r.as_os_str().is_empty() => r,
+            _ => return Err(DotError::InvalidMove(new.display().to_string()).into()),
+        };
+        // excludes under moved path follow it
+        let mut renamed = Vec::new();
+        for exclude in &mut self.files[idx].exclude {
+            if let Ok(rest) = Path::new(exclude.as_str()).strip_prefix(&rel) {
+                let moved = if rest.as_os_str().is_empty() {
+                    new_rel.to_owned()
+                } else {
+                    new_rel.join(rest)
+                };
+                let moved = moved.display().to_string();
+                renamed.push((std::mem::replace(exclude, moved.to_owned()), moved));
+            }
+        }
+        let file = self.files[idx].clone();
+        for (old, new) in renamed {
+            self.rekey_exclude(&file, &old, &new);
+        }
+        let base = file.location.storage(file.glob_base());
+        Ok((base.join(&rel), base.join(&new_rel)))
+    }
+
+    // Returns entry index and pattern relative to entry root
+    // TODO: clean this
+    fn entry_pattern(&self, ctx: &Context, pattern: &str) -> Result<(usize, String)> {
+        let _ = glob::Pattern::new(pattern).map_err(|e| DotError::wrap(pattern, e))?;
+        let pattern_path = PathBuf::from(pattern);
+        let mut components_left = Vec::new();
+        let mut components_right = Vec::new();
+        let mut left = true;
+        for part in pattern_path.components() {
+            use std::path::Component;
+            match part {
+                Component::Normal(s) => {
+                    if let Some(s) = s.to_str() {
+                        if left && is_pattern(s) {
+                            left = false;
+                        }
+                        if left {
+                            components_left.push(part);
+                        } else {
+                            components_right.push(part);
+                        }
+                    }
+                }
+                c => components_left.push(c),
+            }
+        }
+        let root_path = components_left.into_iter().collect::<PathBuf>();
+        let (path, loc) = ctx.abs_clean_path(root_path)?;
+        let path = ctx.get_path(&loc)?.join(path);
+        match self.entry_at(ctx, &path) {
+            Some((i, mut rel)) => {
+                if !components_right.is_empty() {
+                    let trimmed_path = components_right.into_iter().collect::<PathBuf>();
+                    rel = rel.join(trimmed_path);
+                }
+                Ok((i, rel.display().to_string()))
+            }
+            None => Err(DotError::NoMatch(pattern.to_string()).into()),
+        }
+    }
+
+    // Returns sync settings of file, including global excludes
+    pub fn settings(&self, file: &File) -> Result<SyncSettings> {
+        file.settings()?.global(&self.exclude)
+    }
+
+    pub fn add_exclude(&mut self, ctx: &Context, exclude: &str) -> Result<()> {
+        let (idx, exclude) = self.entry_pattern(ctx, exclude.trim())?;
+        let file = &mut self.files[idx];
+        if !file.exclude.contains(&exclude) {
+            file.exclude.push(exclude);
+        }
+        Ok(())
+    }
+
+    pub fn remove_exclude(&mut self, ctx: &Context, exclude: &str) -> Result<()> {
+        let (idx, pattern) = self.entry_pattern(ctx, exclude.trim())?;
+        let file = &mut self.files[idx];
+        match file.exclude.iter().position(|e| e == &pattern) {
+            Some(i) => {
+                file.exclude.remove(i);
+                Ok(())
+            }
+            None => Err(DotError::NoMatch(exclude.to_string()).into()),
+        }
+    }
+
+    // Add exclude applied to every entry, pattern is kept as is
+    pub fn add_global_exclude(&mut self, exclude: &str) -> Result<()> {
+        let exclude = exclude.trim();
+        let _ = glob::Pattern::new(exclude).map_err(|e| DotError::wrap(exclude, e))?;
+        if !self.exclude.iter().any(|e| e == exclude) {
+            self.exclude.push(exclude.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn remove_global_exclude(&mut self, exclude: &str) -> Result<()> {
+        let exclude = exclude.trim();
+        match self.exclude.iter().position(|e| e == exclude) {
+            Some(i) => {
+                self.exclude.remove(i);
+                Ok(())
+            }
+            None => Err(DotError::NoMatch(exclude.to_string()).into()),
+        }
+    }
+
+    // Add include pattern to entry, patterns starting with `!` are negated
+    pub fn add_include(&mut self, ctx: &Context, include: &str) -> Result<()> {
+        let include = include.trim();
+        let (negate, pattern) = match include.strip_prefix('!') {
+            Some(p) => ("!", p),
+            None => ("", include),
+        };
+        let (idx, pattern) = self.entry_pattern(ctx, pattern)?;
+        let include = format!("{}{}", negate, pattern);
+        let file = &mut self.files[idx];
+        // order matters, re-adding a pattern moves it last
+        file.include.retain(|i| i != &include);
+        file.include.push(include);
+        Ok(())
+    }
+
+    pub fn escalate(&self) -> &str {
+        self.escalate
+            .as_deref()
+            .unwrap_or(crate::privilege::DEFAULT_ESCALATE)
+    }
+
+    pub fn max_deletions(&self) -> Option<usize> {
+        self.max_deletions.filter(|max| *max > 0)
+    }
+
+    pub fn trash(&self) -> bool {
+        self.trash.unwrap_or(false)
+    }
+
+    pub fn git(&self) -> Option<&Git> {
+        self.git.as_ref()
+    }
+
+    pub fn git_mut(&mut self) -> Option<&mut Git> {
+        self.git.as_mut()
+    }
+
+    pub fn git_dir(&self, ctx: &Context) -> Option<PathBuf> {
+        self.git
+            .as_ref()
+            .and_then(|g| ctx.get_path(&g.location).ok().map(|p| p.join(&g.path)))
+    }
+
+    pub fn set_git_dir<P: AsRef<Path>>(&mut self, ctx: &Context, path: P) {
+        let (path, loc) = ctx.clean_path(path.as_ref());
+        // commit settings are kept when git dir moves
+        let git = self.git.take().unwrap_or(Git {
+            path: PathBuf::new(),
+            location: Location::Home,
+            subject: None,
+            body: None,
+            commit: Granularity::Run,
+            remote: None,
+            branch: None,
+            push: false,
+            pull: false,
+            bare: false,
+        });
+        self.git = Some(Git {
+            path,
+            location: loc,
+            ..git
+        });
+    }
+}
+
+#[derive(Clone)]
+pub struct Context {
+    pub home: PathBuf,
+    pub xdg_config: PathBuf,
+    pub xdg_data: PathBuf,
+    pub xdg_state: PathBuf,
+    pub xdg_cache: PathBuf,
+    pub bin: PathBuf,
+    // user defined locations with their expanded base
+    pub locations: Vec<(String, PathBuf)>,
+    // path to dot directory
+    pub dot: PathBuf,
+    pub dot_config: PathBuf,
+    // sandbox directory every system location is re-rooted under
+    pub root: Option<PathBuf>,
+    // git work tree is home, managed files are committed in place
+    pub bare: bool,
+}
+
+pub fn absolute(path: &Path) -> Result<PathBuf> {
+    let abs =
+        path_abs::PathAbs::new(path).map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+    Ok(abs.as_path().to_owned())
+}
+
+// Returns path moved under root
+fn reroot(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    // Use home as home directory, XDG directories are reset to their default under it and
+    // so is dot directory, unless $DOT_PATH is set
+    pub fn set_home<P: AsRef<Path>>(&mut self, home: P) -> Result<()> {
+        let home = absolute(home.as_ref())?;
+        self.xdg_config = home.join(".config");
+        self.xdg_data = home.join(".local/share");
+        self.xdg_state = home.join(".local/state");
+        self.xdg_cache = home.join(".cache");
+        self.bin = home.join(".local/bin");
+        // $DOT_PATH still wins over the default dot directory of new home
+        if env::var_os("DOT_PATH").is_none() {
+            self.set_dot(home.join(".dot"))?;
+        }
+        self.home = home;
+        Ok(())
+    }
+
+    pub fn set_dot<P: AsRef<Path>>(&mut self, dot: P) -> Result<()> {
+        self.dot = absolute(dot.as_ref())?;
+        self.dot_config = self.dot.join("config.yml");
+        Ok(())
+    }
+
+    // Re-root all system locations under root, including absolute one. A previous
+    // root is replaced. Dot directory is not a system location and is kept as is.
+    pub fn set_root<P: AsRef<Path>>(&mut self, root: P) -> Result<()> {
+        let root = absolute(root.as_ref())?;
+        let old = self.root.take().unwrap_or_else(|| PathBuf::from("/"));
+        let unroot = |path: &Path| match path.strip_prefix(&old) {
+            Ok(rest) => Path::new("/").join(rest),
+            Err(_) => path.to_owned(),
+        };
+        for base in &mut [
+            &mut self.home,
+            &mut self.xdg_config,
+            &mut self.xdg_data,
+            &mut self.xdg_state,
+            &mut self.xdg_cache,
+            &mut self.bin,
+        ] {
+            **base = reroot(&root, &unroot(base));
+        }
+        for (_, base) in &mut self.locations {
+            *base = reroot(&root, &unroot(base));
+        }
+        self.root = Some(root);
+        Ok(())
+    }
+
+    pub fn abs_clean_path<P: AsRef<Path>>(&self, path: P) -> Result<(PathBuf, Location)> {
+        let path: &Path = path.as_ref();
+        let abs = path_abs::PathAbs::new(path)
+            .map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        let path = abs.as_path();
+        Ok(self.clean_path(path))
+    }
+
+    // Load user defined locations from config
+    pub fn set_locations(&mut self, locations: &BTreeMap<String, String>) -> Result<()> {
+        self.locations.clear();
+        for (name, base) in locations {
+            if Location::builtin(name).is_some() {
+                return Err(DotError::ReservedLocation(name.to_owned()).into());
+            }
+            match expand_path(base) {
+                Ok(p) if p.is_absolute() => {
+                    let p = match &self.root {
+                        Some(root) => reroot(root, &p),
+                        None => p,
+                    };
+                    self.locations.push((name.to_owned(), p));
+                }
+                Ok(p) => eprintln!("!=> location {} is not absolute: {}", name, p.display()),
+                Err(e) => eprintln!("!=> location {} is unavailable: {}", name, e),
+            }
+        }
+        Ok(())
+    }
+
+    // All locations bases, absolute is not included
+    pub fn bases(&self) -> Vec<(Location, &Path)> {
+        let mut bases = vec![
+            (Location::Home, self.home.as_path()),
+            (Location::Config, self.xdg_config.as_path()),
+            (Location::Data, self.xdg_data.as_path()),
+            (Location::State, self.xdg_state.as_path()),
+            (Location::Cache, self.xdg_cache.as_path()),
+            (Location::Bin, self.bin.as_path()),
+        ];
+        for (name, base) in &self.locations {
+            bases.push((Location::Named(name.to_owned()), base.as_path()));
+        }
+        bases
+    }
+
+    pub fn clean_path<P: AsRef<Path>>(&self, path: P) -> (PathBuf, Location) {
+        let path = path.as_ref();
+        let home;
+        let mut path = path;
+        if let Ok(p) = path.strip_prefix("~") {
+            home = self.home.join(p);
+            path = &home;
+        }
+        // most specific base wins
+        let best = self
+            .bases()
+            .into_iter()
+            .filter(|(_, base)| path.starts_with(base))
+            .max_by_key(|(_, base)| base.components().count());
+        if let Some((loc, base)) = best {
+            return (path.strip_prefix(base).unwrap().to_owned(), loc);
+        }
+        let root = self.root.as_deref().unwrap_or_else(|| Path::new("/"));
+        let rel = path
+            .strip_prefix(root)
+            .or_else(|_| path.strip_prefix("/"))
+            .unwrap_or(path);
+        (rel.to_owned(), Location::Absolute)
+    }
+
+    // Path relative to home, bare mode can only manage files in home
+    pub fn home_path(&self, path: &Path) -> Result<PathBuf> {
+        path.strip_prefix(&self.home)
+            .map(Path::to_owned)
+            .map_err(|_| DotError::OutsideHome(path.display().to_string()).into())
+    }
+
+    // Git work tree, dot directory unless in bare mode
+    pub fn work_tree(&self) -> &Path {
+        if self.bare {
+            &self.home
+        } else {
+            &self.dot
+        }
+    }
+
+    pub fn get_path(&self, loc: &Location) -> Result<PathBuf> {
+        if let Location::Absolute = loc {
+            return Ok(self.root.clone().unwrap_or_else(|| PathBuf::from("/")));
+        }
+        self.bases()
+            .into_iter()
+            .find(|(l, _)| l == loc)
+            .map(|(_, base)| base.to_owned())
+            .ok_or_else(|| DotError::UnknownLocation(loc.to_string()).into())
+    }
+
+    // Path of an entry in dot directory
+    pub fn dot_path<P: AsRef<Path>>(&self, loc: &Location, path: P) -> PathBuf {
+        self.dot.join(loc.storage(path))
+    }
+
+    // Location may point outside home (e.g /etc), installing there can need
+    // elevated privileges
+    pub fn is_system(&self, loc: &Location) -> bool {
+        match loc {
+            Location::Absolute => true,
+            Location::Named(_) => self
+                .get_path(loc)
+                .map(|base| !base.starts_with(&self.home))
+                .unwrap_or(true),
+            _ => false,
+        }
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        let home = env::var("HOME").expect("HOME variable is not set.");
+        let config = env::var("XDG_CONFIG_HOME").unwrap_or_else(|_| format!("{}/.config", home));
+        let data = env::var("XDG_DATA_HOME").unwrap_or_else(|_| format!("{}/.local/share", home));
+        let state = env::var("XDG_STATE_HOME").unwrap_or_else(|_| format!("{}/.local/state", home));
+        let cache = env::var("XDG_CACHE_HOME").unwrap_or_else(|_| format!("{}/.cache", home));
+        let bin = env::var("XDG_BIN_HOME").unwrap_or_else(|_| format!("{}/.local/bin", home));
+        let dot = env::var("DOT_PATH").unwrap_or_else(|_| format!("{}/.dot", home));
+        Context {
+            home: PathBuf::from(home),
+            xdg_config: PathBuf::from(config),
+            xdg_data: PathBuf::from(data),
+            xdg_state: PathBuf::from(state),
+            xdg_cache: PathBuf::from(cache),
+            bin: PathBuf::from(bin),
+            locations: Vec::new(),
+            dot: PathBuf::from(&dot),
+            dot_config: PathBuf::from(&dot).join("config.yml"),
+            root: None,
+            bare: false,
+        }
+    }
+}
@@ -1,3 +1,6 @@
+// impls generated by failure_derive are not local to their item
+#![allow(non_local_definitions)]
+
 use failure::Error;
 use failure_derive::Fail;
 
@@ -11,12 +14,75 @@ pub enum DotError {
     Wrap { msg: String, error: Error },
     #[fail(display = "no file have matched for path {}", 0)]
     NoMatch(String),
-    #[fail(display = "cannot checksum directory")]
-    ChecksumDir,
     #[fail(display = "path {} does not exists", 0)]
     NotFound(String),
-    #[fail(display = "cannot copy different file type")]
-    InvalidCopy,
+    #[fail(display = "location {} is not defined or unavailable", 0)]
+    UnknownLocation(String),
+    #[fail(display = "{} is already managed", 0)]
+    AlreadyManaged(String),
+    #[fail(
+        display = "cannot move to {}, destination must be in the same entry",
+        0
+    )]
+    InvalidMove(String),
+    #[fail(display = "{} already exists", 0)]
+    AlreadyExists(String),
+    #[fail(display = "cannot add base directory of location {}", 0)]
+    LocationRoot(String),
+    #[fail(display = "location name {} is reserved", 0)]
+    ReservedLocation(String),
+    #[fail(display = "{} overlaps managed entry {}", 0, 1)]
+    Overlaps(String, String),
+    #[fail(display = "invalid privileged operation: {}", 0)]
+    InvalidOperation(String),
+    #[fail(display = "privileged command {} failed", 0)]
+    PrivilegedFailed(String),
+    #[fail(display = "cannot relocate dot directory inside itself: {}", 0)]
+    InvalidRelocation(String),
+    #[fail(display = "refusing to delete {}", 0)]
+    UnsafeDeletion(String),
+    #[fail(
+        display = "refusing to delete {} files, more than max_deletions ({})",
+        0, 1
+    )]
+    TooManyDeletions(usize, usize),
+    #[fail(display = "no run {} in history", 0)]
+    UnknownRun(String),
+    #[fail(display = "no install or update left to undo")]
+    NothingToUndo,
+    #[fail(display = "run {} is already undone", 0)]
+    AlreadyUndone(String),
+    #[fail(
+        display = "run {} has files that could not be backed up, it cannot be undone",
+        0
+    )]
+    NotUndoable(String),
+    #[fail(display = "files changed since run {}, cannot undo it", 0)]
+    ChangedSinceRun(String),
+    #[fail(display = "git {} failed", 0)]
+    GitFailed(String),
+    #[fail(display = "tar {} failed", 0)]
+    TarFailed(String),
+    #[fail(display = "no git remote configured, see `dotto remote set`")]
+    NoRemote,
+    #[fail(
+        display = "local branch and {} have diverged ({} local, {} remote commit(s)), merge them with `dotto git pull {0}`",
+        0, 1, 2
+    )]
+    Diverged(String, usize, usize),
+    #[fail(
+        display = "{} has {} new commit(s), pull them first with `dotto remote pull`",
+        0, 1
+    )]
+    BehindRemote(String, usize),
+    #[fail(display = "{} does not exist at revision {}", 0, 1)]
+    NotInRev(String, String),
+    #[fail(display = "{} is outside home, it cannot be managed in bare mode", 0)]
+    OutsideHome(String),
+    #[fail(display = "{} is not available in bare mode", 0)]
+    BareMode(String),
+    #[fail(display = "plan is outdated: {}, plan again", 0)]
+    PlanChanged(String),
 }
 
 impl DotError {
@@ -0,0 +1,471 @@
+//! Helpers to run git on dot directory
+
+use crate::config::{Context, Git};
+use crate::error::{DotError, Result};
+use crate::sync::{Diff, DiffKind};
+use std::ffi::{CStr, OsStr};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Returns a git command running in dot context
+pub fn command<P: AsRef<Path>>(ctx: &Context, git_dir: P) -> process::Command {
+    let mut cmd = process::Command::new("git");
+    cmd.arg("--git-dir")
+        .arg(git_dir.as_ref())
+        .arg("--work-tree")
+        .arg(ctx.work_tree());
+    cmd
+}
+
+// Stage changes of paths (relative to work tree), everything if paths is empty.
+// Only tracked files are staged when paths is empty in bare mode, the whole
+// home is not added.
+// Git output goes to stderr, stdout may be machine readable.
+pub fn stage<P: AsRef<Path>>(ctx: &Context, git_dir: P, paths: &[PathBuf]) -> Result<()> {
+    let git_dir = git_dir.as_ref();
+    match known(ctx, git_dir, paths)? {
+        Some(paths) if paths.is_empty() => Ok(()),
+        known => stage_known(ctx, git_dir, known.as_deref().unwrap_or_default()),
+    }
+}
+
+fn stage_known(ctx: &Context, git_dir: &Path, paths: &[PathBuf]) -> Result<()> {
+    let status = command(ctx, git_dir)
+        .current_dir(ctx.work_tree())
+        .arg("add")
+        .arg(if ctx.bare && paths.is_empty() {
+            "-u"
+        } else {
+            "-A"
+        })
+        .arg("--")
+        .args(paths)
+        .stdout(io::stderr())
+        .status()?;
+    if !status.success() {
+        return Err(DotError::GitFailed("add".to_owned()).into());
+    }
+    Ok(())
+}
+
+// Paths git can match, None if paths is empty. Paths neither tracked nor holding
+// files (e.g empty directories) would make git fail.
+fn known(ctx: &Context, git_dir: &Path, paths: &[PathBuf]) -> Result<Option<Vec<PathBuf>>> {
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    let out = command(ctx, git_dir)
+        .current_dir(ctx.work_tree())
+        .arg("ls-files")
+        .arg("-z")
+        .arg("--cached")
+        .arg("--others")
+        .arg("--exclude-standard")
+        .arg("--")
+        .args(paths)
+        .output()?;
+    if !out.status.success() {
+        return Err(DotError::GitFailed("ls-files".to_owned()).into());
+    }
+    let files = split_paths(&out.stdout);
+    let known = paths
+        .iter()
+        .filter(|p| files.iter().any(|f| f.starts_with(p)))
+        .cloned()
+        .collect();
+    Ok(Some(known))
+}
+
+fn split_paths(out: &[u8]) -> Vec<PathBuf> {
+    out.split(|b| *b == 0)
+        .filter(|p| !p.is_empty())
+        .map(|p| PathBuf::from(OsStr::from_bytes(p)))
+        .collect()
+}
+
+// Track and stage paths (relative to work tree), new files included
+pub fn add<P: AsRef<Path>>(ctx: &Context, git_dir: P, paths: &[PathBuf]) -> Result<()> {
+    let mut cmd = command(ctx, git_dir);
+    cmd.current_dir(ctx.work_tree())
+        .arg("add")
+        .arg("-A")
+        .arg("--")
+        .args(paths);
+    run(cmd, "add")
+}
+
+// Stop tracking paths (relative to work tree), files are kept in work tree
+pub fn untrack<P: AsRef<Path>>(ctx: &Context, git_dir: P, paths: &[PathBuf]) -> Result<()> {
+    let mut cmd = command(ctx, git_dir);
+    cmd.current_dir(ctx.work_tree())
+        .arg("rm")
+        .arg("-r")
+        .arg("--cached")
+        .arg("--quiet")
+        .arg("--ignore-unmatch")
+        .arg("--")
+        .args(paths);
+    run(cmd, "rm")
+}
+
+// Tracked files matching paths (relative to work tree)
+pub fn tracked<P: AsRef<Path>>(
+    ctx: &Context,
+    git_dir: P,
+    paths: &[PathBuf],
+) -> Result<Vec<PathBuf>> {
+    let out = command(ctx, git_dir)
+        .current_dir(ctx.work_tree())
+        .arg("ls-files")
+        .arg("-z")
+        .arg("--")
+        .args(paths)
+        .output()?;
+    if !out.status.success() {
+        return Err(DotError::GitFailed("ls-files".to_owned()).into());
+    }
+    Ok(split_paths(&out.stdout))
+}
+
+// Changes of paths (relative to work tree) from git status, untracked files are
+// only listed when asked
+pub fn changes<P: AsRef<Path>>(
+    ctx: &Context,
+    git_dir: P,
+    paths: &[PathBuf],
+    untracked: bool,
+) -> Result<Vec<Diff>> {
+    let out = command(ctx, git_dir)
+        .current_dir(ctx.work_tree())
+        .arg("status")
+        .arg("--porcelain")
+        .arg("-z")
+        .arg(if untracked {
+            "--untracked-files=all"
+        } else {
+            "--untracked-files=no"
+        })
+        .arg("--")
+        .args(paths)
+        .output()?;
+    if !out.status.success() {
+        return Err(DotError::GitFailed("status".to_owned()).into());
+    }
+    let mut diffs = Vec::new();
+    // entries are `XY path`, renames are followed by their source path
+    let mut entries = out.stdout.split(|b| *b == 0).filter(|e| e.len() > 3);
+    while let Some(entry) = entries.next() {
+        let (x, y) = (entry[0], entry[1]);
+        let path = PathBuf::from(OsStr::from_bytes(&entry[3..]));
+        let kind = match (x, y) {
+            (b'D', _) | (_, b'D') => DiffKind::Deleted,
+            (b'?', _) | (b'A', _) => DiffKind::Added,
+            (b'R', _) | (b'C', _) => {
+                entries.next();
+                DiffKind::Added
+            }
+            _ => DiffKind::Modified,
+        };
+        diffs.push(Diff::new(path, kind));
+    }
+    Ok(diffs)
+}
+
+// Stage and commit only changes of paths (relative to work tree)
+pub fn commit_paths<P: AsRef<Path>>(
+    ctx: &Context,
+    git_dir: P,
+    message: &str,
+    paths: &[PathBuf],
+) -> Result<()> {
+    let git_dir = git_dir.as_ref();
+    let paths = match known(ctx, git_dir, paths)? {
+        Some(paths) if paths.is_empty() => return Ok(()),
+        known => known.unwrap_or_default(),
+    };
+    stage_known(ctx, git_dir, &paths)?;
+    commit_index(ctx, git_dir, message, &paths)
+}
+
+// Stage tracked files of paths (relative to work tree) and commit the whole index,
+// e.g to commit files removed from index but kept in work tree
+pub fn commit_staged<P: AsRef<Path>>(
+    ctx: &Context,
+    git_dir: P,
+    message: &str,
+    paths: &[PathBuf],
+) -> Result<()> {
+    let git_dir = git_dir.as_ref();
+    if !paths.is_empty() {
+        let tracked = tracked(ctx, git_dir, paths)?;
+        if !tracked.is_empty() {
+            stage_known(ctx, git_dir, &tracked)?;
+        }
+    }
+    commit_index(ctx, git_dir, message, &[])
+}
+
+// Commit staged changes of paths, the whole index if paths is empty
+fn commit_index(ctx: &Context, git_dir: &Path, message: &str, paths: &[PathBuf]) -> Result<()> {
+    // nothing to commit is not a failure, e.g changes ignored by git
+    let unchanged = command(ctx, git_dir)
+        .current_dir(ctx.work_tree())
+        .arg("diff")
+        .arg("--cached")
+        .arg("--quiet")
+        .arg("--")
+        .args(paths)
+        .status()?;
+    if unchanged.success() {
+        return Ok(());
+    }
+    let mut proc = command(ctx, git_dir)
+        .current_dir(ctx.work_tree())
+        .arg("commit")
+        .arg("-F")
+        .arg("-")
+        .arg("--")
+        .args(paths)
+        .stdin(process::Stdio::piped())
+        .stdout(io::stderr())
+        .spawn()?;
+    proc.stdin.as_mut().unwrap().write_all(message.as_bytes())?;
+    if !proc.wait()?.success() {
+        return Err(DotError::GitFailed("commit".to_owned()).into());
+    }
+    Ok(())
+}
+
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if res != 0 {
+        return String::new();
+    }
+    CStr::from_bytes_until_nul(&buf)
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Replace {name} variables in template, unknown variables are kept as is
+pub fn render(template: &str, vars: &[(&str, String)]) -> String {
+    let mut out = template.to_owned();
+    for (name, value) in vars {
+        out = out.replace(&format!("{{{}}}", name), value);
+    }
+    out
+}
+
+// Run git command, fails on non zero exit status
+fn run(mut cmd: process::Command, what: &str) -> Result<()> {
+    if !cmd.stdout(io::stderr()).status()?.success() {
+        return Err(DotError::GitFailed(what.to_owned()).into());
+    }
+    Ok(())
+}
+
+// Run git command and returns its trimmed output, none on failure
+fn output(mut cmd: process::Command) -> Result<Option<String>> {
+    let out = cmd.stderr(process::Stdio::null()).output()?;
+    if !out.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&out.stdout).trim().to_owned()))
+}
+
+// Remote and branch to sync dot repository with
+pub struct Remote {
+    pub remote: String,
+    pub branch: String,
+}
+
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.remote, self.branch)
+    }
+}
+
+// State of local branch compared to remote one
+#[derive(Debug, PartialEq)]
+pub enum Divergence {
+    // remote branch does not exist yet
+    Missing,
+    // no local commit yet
+    Unborn,
+    // commits only on (local, remote) side
+    Commits(usize, usize),
+}
+
+impl Remote {
+    // Remote from git config, branch defaults to current one
+    pub fn new<P: AsRef<Path>>(ctx: &Context, git_dir: P, git: &Git) -> Result<Remote> {
+        let remote = git.remote.clone().ok_or(DotError::NoRemote)?;
+        let branch = match &git.branch {
+            Some(b) => b.to_owned(),
+            None => {
+                let mut cmd = command(ctx, git_dir);
+                cmd.arg("symbolic-ref").arg("--short").arg("HEAD");
+                output(cmd)?.ok_or_else(|| DotError::GitFailed("symbolic-ref".to_owned()))?
+            }
+        };
+        Ok(Remote { remote, branch })
+    }
+
+    // Fetch remote branch into FETCH_HEAD and compare it with HEAD
+    pub fn fetch<P: AsRef<Path>>(&self, ctx: &Context, git_dir: P) -> Result<Divergence> {
+        let git_dir = git_dir.as_ref();
+        let mut cmd = command(ctx, git_dir);
+        cmd.arg("ls-remote")
+            .arg("--heads")
+            .arg(&self.remote)
+            .arg(&self.branch);
+        let heads = output(cmd)?.ok_or_else(|| DotError::GitFailed("ls-remote".to_owned()))?;
+        if heads.is_empty() {
+            return Ok(Divergence::Missing);
+        }
+        let mut cmd = command(ctx, git_dir);
+        cmd.arg("fetch")
+            .arg("--quiet")
+            .arg(&self.remote)
+            .arg(&self.branch);
+        run(cmd, "fetch")?;
+        let mut cmd = command(ctx, git_dir);
+        cmd.arg("rev-parse")
+            .arg("--verify")
+            .arg("--quiet")
+            .arg("HEAD");
+        if output(cmd)?.is_none() {
+            return Ok(Divergence::Unborn);
+        }
+        let mut cmd = command(ctx, git_dir);
+        cmd.arg("rev-list")
+            .arg("--left-right")
+            .arg("--count")
+            .arg("HEAD...FETCH_HEAD");
+        let counts = output(cmd)?.ok_or_else(|| DotError::GitFailed("rev-list".to_owned()))?;
+        let mut counts = counts.split_whitespace().map(|c| c.parse().unwrap_or(0));
+        let local = counts.next().unwrap_or(0);
+        let remote = counts.next().unwrap_or(0);
+        Ok(Divergence::Commits(local, remote))
+    }
+
+    // Fast forward local branch to remote one, refused when branches have diverged.
+    // Returns false when there was nothing to pull.
+    pub fn pull<P: AsRef<Path>>(&self, ctx: &Context, git_dir: P) -> Result<bool> {
+        let git_dir = git_dir.as_ref();
+        match self.fetch(ctx, git_dir)? {
+            Divergence::Missing | Divergence::Commits(_, 0) => return Ok(false),
+            Divergence::Commits(local, remote) if local > 0 => {
+                return Err(DotError::Diverged(self.to_string(), local, remote).into())
+            }
+            _ => {}
+        }
+        let mut cmd = command(ctx, git_dir);
+        cmd.current_dir(ctx.work_tree())
+            .arg("merge")
+            .arg("--ff-only")
+            .arg("--quiet")
+            .arg("FETCH_HEAD");
+        run(cmd, "merge")?;
+        Ok(true)
+    }
+
+    // Fails when remote has commits missing locally, local branch could not be
+    // pushed then
+    pub fn check_push<P: AsRef<Path>>(&self, ctx: &Context, git_dir: P) -> Result<Divergence> {
+        match self.fetch(ctx, git_dir)? {
+            Divergence::Commits(local, remote) if remote > 0 && local > 0 => {
+                Err(DotError::Diverged(self.to_string(), local, remote).into())
+            }
+            Divergence::Commits(_, remote) if remote > 0 => {
+                Err(DotError::BehindRemote(self.to_string(), remote).into())
+            }
+            divergence => Ok(divergence),
+        }
+    }
+
+    // Push local branch, refused when remote has commits missing locally.
+    // Returns false when there was nothing to push.
+    pub fn push<P: AsRef<Path>>(&self, ctx: &Context, git_dir: P) -> Result<bool> {
+        let git_dir = git_dir.as_ref();
+        match self.check_push(ctx, git_dir)? {
+            Divergence::Unborn | Divergence::Commits(0, _) => return Ok(false),
+            _ => {}
+        }
+        let mut cmd = command(ctx, git_dir);
+        cmd.arg("push")
+            .arg("--quiet")
+            .arg(&self.remote)
+            .arg(format!("HEAD:refs/heads/{}", self.branch));
+        run(cmd, "push")?;
+        Ok(true)
+    }
+}
+
+// Write files under path (relative to work tree) as they were at rev into dest,
+// returns false if path did not exist at rev
+pub fn checkout_to<P: AsRef<Path>>(
+    ctx: &Context,
+    git_dir: P,
+    rev: &str,
+    path: &Path,
+    dest: &Path,
+) -> Result<bool> {
+    let git_dir = git_dir.as_ref();
+    let mut cmd = command(ctx, git_dir);
+    cmd.current_dir(ctx.work_tree())
+        .arg("ls-tree")
+        .arg("-r")
+        .arg("-z")
+        .arg(rev)
+        .arg("--")
+        .arg(path);
+    let out = cmd.stderr(process::Stdio::inherit()).output()?;
+    if !out.status.success() {
+        return Err(DotError::GitFailed("ls-tree".to_owned()).into());
+    }
+    let mut found = false;
+    // entries are `<mode> <type> <object>\t<path>`
+    for entry in out.stdout.split(|b| *b == 0).filter(|e| !e.is_empty()) {
+        let tab = match entry.iter().position(|b| *b == b'\t') {
+            Some(t) => t,
+            None => continue,
+        };
+        let meta = String::from_utf8_lossy(&entry[..tab]).into_owned();
+        let mut meta = meta.split_whitespace();
+        let (mode, kind, object) = (meta.next(), meta.next(), meta.next());
+        let (mode, object) = match (mode, kind, object) {
+            (Some(m), Some("blob"), Some(o)) => (m, o),
+            _ => continue,
+        };
+        let file = dest.join(Path::new(OsStr::from_bytes(&entry[tab + 1..])));
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let blob = command(ctx, git_dir)
+            .arg("cat-file")
+            .arg("blob")
+            .arg(object)
+            .output()?;
+        if !blob.status.success() {
+            return Err(DotError::GitFailed("cat-file".to_owned()).into());
+        }
+        // symlinks are stored as blobs holding their target
+        if mode == "120000" {
+            symlink(OsStr::from_bytes(&blob.stdout), &file)?;
+            found = true;
+            continue;
+        }
+        fs::write(&file, blob.stdout)?;
+        if mode == "100755" {
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o755))?;
+        }
+        found = true;
+    }
+    Ok(found)
+}
@@ -0,0 +1,285 @@
+//! Operation log of install and update runs, with before-images to undo them
+
+use crate::cmd::{roots, Direction, SyncContext};
+use crate::config::Context;
+use crate::error::{DotError, Result};
+use crate::plan::State;
+use crate::privilege::Operation;
+use crate::sync::DiffKind;
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+const RUN_FILE: &str = "run.yml";
+// runs kept in history, older ones are removed with their before-images
+const MAX_RUNS: usize = 20;
+
+// A path touched by a run, with its state before and after it
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Change {
+    pub path: PathBuf,
+    pub before: State,
+    pub after: State,
+    // before-image of a file, relative to run directory
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub backup: Option<PathBuf>,
+}
+
+impl Change {
+    pub fn kind(&self) -> DiffKind {
+        match (&self.before, &self.after) {
+            (State::Missing, _) => DiffKind::Added,
+            (_, State::Missing) => DiffKind::Deleted,
+            _ => DiffKind::Modified,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Run {
+    pub id: String,
+    pub date: String,
+    pub direction: Direction,
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub undone: bool,
+    // some before-images could not be saved, run cannot be undone
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub incomplete: bool,
+    pub changes: Vec<Change>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+// Directory containing all runs
+pub fn history_dir(ctx: &Context) -> PathBuf {
+    ctx.xdg_data.join("dotto/history")
+}
+
+// Create or truncate a file only readable by user
+fn private_file(path: &Path) -> Result<fs::File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| DotError::wrap(path.display().to_string(), e).into())
+}
+
+// Sort key of run id, runs in the same second are numbered from 2
+fn order(id: &str) -> (&str, u32) {
+    match id.split_once('.') {
+        Some((stamp, n)) => (stamp, n.parse().unwrap_or(0)),
+        None => (id, 1),
+    }
+}
+
+// Ids of runs in history, oldest first
+fn run_ids(base: &Path) -> Result<Vec<String>> {
+    let mut ids = fs::read_dir(base)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().join(RUN_FILE).is_file())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    ids.sort_by(|a, b| order(a).cmp(&order(b)));
+    Ok(ids)
+}
+
+// Remove oldest runs beyond MAX_RUNS
+fn prune(base: &Path) -> Result<()> {
+    let ids = run_ids(base)?;
+    let old = ids.len().saturating_sub(MAX_RUNS);
+    for id in &ids[..old] {
+        fs::remove_dir_all(base.join(id))?;
+    }
+    Ok(())
+}
+
+impl Run {
+    // Record before-images of every path sync_ctx is going to touch
+    pub fn start(ctx: &Context, direction: Direction, sync_ctx: &[SyncContext]) -> Result<Run> {
+        let date = crate::util::now();
+        let base = history_dir(ctx);
+        fs::create_dir_all(&base)?;
+        // run ids are sortable, suffixed when runs happen in the same second.
+        // Numbers follow the last run of that second, pruned ones are not reused.
+        let stamp = date.replace(['-', ':'], "");
+        let mut n = run_ids(&base)?
+            .iter()
+            .map(|id| order(id))
+            .filter(|(s, _)| *s == stamp)
+            .map(|(_, n)| n + 1)
+            .max()
+            .unwrap_or(1);
+        let (id, dir) = loop {
+            let id = if n > 1 {
+                format!("{}.{}", stamp, n)
+            } else {
+                stamp.to_owned()
+            };
+            let dir = base.join(&id);
+            // before-images can be copies of private files
+            match DirBuilder::new().mode(0o700).create(&dir) {
+                Ok(()) => break (id, dir),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(DotError::wrap(dir.display().to_string(), e).into()),
+            }
+        };
+        let mut changes = Vec::new();
+        let mut incomplete = false;
+        for sctx in sync_ctx {
+            let (_, dst) = roots(ctx, &sctx.location, &sctx.path, direction)?;
+            for diff in &sctx.diffs {
+                let path = diff.full_path(&dst);
+                // replaced paths are deleted then added, one change is enough
+                if changes.iter().any(|c: &Change| c.path == path) {
+                    continue;
+                }
+                // privileged files may not be readable, they are applied anyway
+                if path.is_file() && fs::File::open(&path).is_err() {
+                    eprintln!(
+                        "!=> cannot read {}, run {} will not be undoable",
+                        path.display(),
+                        id
+                    );
+                    incomplete = true;
+                    continue;
+                }
+                let before = State::new(&path)?;
+                let backup = match before {
+                    State::File { .. } => {
+                        let backup = PathBuf::from(format!("files/{}", changes.len()));
+                        fs::create_dir_all(dir.join("files"))?;
+                        let mut src = fs::File::open(&path)
+                            .map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+                        io::copy(&mut src, &mut private_file(&dir.join(&backup))?)?;
+                        Some(backup)
+                    }
+                    _ => None,
+                };
+                changes.push(Change {
+                    path,
+                    before,
+                    after: State::Missing,
+                    backup,
+                });
+            }
+        }
+        let run = Run {
+            id,
+            date,
+            direction,
+            undone: false,
+            incomplete,
+            changes,
+            dir,
+        };
+        run.write()?;
+        prune(&base)?;
+        Ok(run)
+    }
+
+    // Record state of touched paths after run, even if it failed halfway
+    pub fn finish(mut self) -> Result<()> {
+        for change in &mut self.changes {
+            match State::new(&change.path) {
+                Ok(state) => change.after = state,
+                Err(e) => {
+                    eprintln!("!=> {}, run {} will not be undoable", e, self.id);
+                    self.incomplete = true;
+                }
+            }
+        }
+        self.write()
+    }
+
+    fn write(&self) -> Result<()> {
+        let file = private_file(&self.dir.join(RUN_FILE))?;
+        serde_yaml::to_writer(file, self).map_err(failure::Error::from)
+    }
+
+    pub fn read<P: AsRef<Path>>(dir: P) -> Result<Run> {
+        let dir = dir.as_ref();
+        let path = dir.join(RUN_FILE);
+        let text =
+            fs::read_to_string(&path).map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        let mut run: Run = serde_yaml::from_str(&text)
+            .map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        run.dir = dir.to_owned();
+        Ok(run)
+    }
+
+    // All recorded runs, oldest first
+    pub fn list(ctx: &Context) -> Result<Vec<Run>> {
+        let entries = match fs::read_dir(history_dir(ctx)) {
+            Ok(e) => e,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut runs = Vec::new();
+        for entry in entries {
+            let dir = entry?.path();
+            if dir.join(RUN_FILE).is_file() {
+                runs.push(Run::read(&dir)?);
+            }
+        }
+        runs.sort_by(|a, b| order(&a.id).cmp(&order(&b.id)));
+        Ok(runs)
+    }
+
+    // Paths that changed since run, they would be lost by an undo
+    pub fn changed(&self) -> Result<Vec<&Path>> {
+        let mut changed = Vec::new();
+        for change in &self.changes {
+            if State::new(&change.path)? != change.after {
+                changed.push(change.path.as_path());
+            }
+        }
+        Ok(changed)
+    }
+
+    // Operations restoring before-images, in reverse order of the run
+    pub fn undo_operations(&self) -> Vec<Operation> {
+        let mut ops = Vec::new();
+        for change in self.changes.iter().rev() {
+            if change.before == change.after {
+                continue;
+            }
+            match (&change.before, &change.backup) {
+                (State::Missing, _) => ops.push(Operation::Remove {
+                    path: change.path.to_owned(),
+                }),
+                (State::Dir, _) => {
+                    if change.after != State::Missing {
+                        ops.push(Operation::Remove {
+                            path: change.path.to_owned(),
+                        });
+                    }
+                    ops.push(Operation::Mkdir {
+                        path: change.path.to_owned(),
+                    })
+                }
+                (State::File { .. }, Some(backup)) => {
+                    if change.after == State::Dir {
+                        ops.push(Operation::Remove {
+                            path: change.path.to_owned(),
+                        });
+                    }
+                    ops.push(Operation::Copy {
+                        src: self.dir.join(backup),
+                        dst: change.path.to_owned(),
+                    })
+                }
+                (State::File { .. }, None) => {}
+            }
+        }
+        ops
+    }
+
+    pub fn mark_undone(&mut self) -> Result<()> {
+        self.undone = true;
+        self.write()
+    }
+}
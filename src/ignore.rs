@@ -0,0 +1,179 @@
+//! Per directory `.dottoignore` files, using gitignore semantics
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub const IGNORE_FILE: &str = ".dottoignore";
+
+#[derive(Debug)]
+struct Rule {
+    pattern: glob::Pattern,
+    negated: bool,
+    // pattern ends with `/`
+    dir_only: bool,
+    // pattern contains a `/`, it is matched from ignore file directory
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(l) => (true, l),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(l) => (true, l),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        let pattern = glob::Pattern::new(line).ok()?;
+        Some(Rule {
+            pattern,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let options = glob::MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        if self.anchored {
+            return self.pattern.matches_path_with(path, options);
+        }
+        path.file_name()
+            .map(|n| self.pattern.matches_path_with(Path::new(n), options))
+            .unwrap_or(false)
+    }
+}
+
+// Rules of one ignore file, base is relative to entry root
+#[derive(Debug)]
+pub struct IgnoreFile {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    // Read ignore file in dir, base is dir relative to entry root
+    pub fn read<P: AsRef<Path>, B: Into<PathBuf>>(dir: P, base: B) -> Option<IgnoreFile> {
+        let content = fs::read_to_string(dir.as_ref().join(IGNORE_FILE)).ok()?;
+        let rules = content.lines().filter_map(Rule::parse).collect::<Vec<_>>();
+        if rules.is_empty() {
+            return None;
+        }
+        Some(IgnoreFile {
+            base: base.into(),
+            rules,
+        })
+    }
+}
+
+// Check if path (relative to entry root) is ignored, files must be ordered from
+// shallowest to deepest. Last matching rule wins.
+pub fn is_ignored(files: &[Rc<IgnoreFile>], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for file in files {
+        let rel = match path.strip_prefix(&file.base) {
+            Ok(r) if !r.as_os_str().is_empty() => r,
+            _ => continue,
+        };
+        for rule in &file.rules {
+            if rule.matches(rel, is_dir) {
+                ignored = !rule.negated;
+            }
+        }
+    }
+    ignored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(base: &str, content: &str) -> Rc<IgnoreFile> {
+        Rc::new(IgnoreFile {
+            base: PathBuf::from(base),
+            rules: content.lines().filter_map(Rule::parse).collect(),
+        })
+    }
+
+    fn ignored(files: &[Rc<IgnoreFile>], path: &str) -> bool {
+        is_ignored(files, Path::new(path), false)
+    }
+
+    #[test]
+    fn parse() {
+        assert!(Rule::parse("").is_none());
+        assert!(Rule::parse("   ").is_none());
+        assert!(Rule::parse("# comment").is_none());
+        let rule = Rule::parse("!build/  ").unwrap();
+        assert!(rule.negated && rule.dir_only && !rule.anchored);
+        assert_eq!(rule.pattern.as_str(), "build");
+        let rule = Rule::parse("/a/*.log").unwrap();
+        assert!(!rule.negated && !rule.dir_only && rule.anchored);
+        assert_eq!(rule.pattern.as_str(), "a/*.log");
+        // escaped leading `!` and `#` are literal
+        assert_eq!(Rule::parse("\\!x").unwrap().pattern.as_str(), "!x");
+        assert!(!Rule::parse("\\!x").unwrap().negated);
+        assert_eq!(Rule::parse("\\#x").unwrap().pattern.as_str(), "#x");
+    }
+
+    #[test]
+    fn names_match_at_any_depth() {
+        let files = [file("", "*.log\n")];
+        assert!(ignored(&files, "a.log"));
+        assert!(ignored(&files, "a/b/c.log"));
+        assert!(!ignored(&files, "a.log/c"));
+    }
+
+    #[test]
+    fn anchored() {
+        let files = [file("", "/build\na/*.tmp\n")];
+        assert!(ignored(&files, "build"));
+        assert!(!ignored(&files, "src/build"));
+        assert!(ignored(&files, "a/x.tmp"));
+        assert!(!ignored(&files, "a/b/x.tmp"));
+        assert!(!ignored(&files, "b/a/x.tmp"));
+    }
+
+    #[test]
+    fn directories_only() {
+        let files = [file("", "cache/\n")];
+        assert!(is_ignored(&files, Path::new("a/cache"), true));
+        assert!(!is_ignored(&files, Path::new("a/cache"), false));
+    }
+
+    #[test]
+    fn last_match_wins() {
+        let files = [file("", "*.log\n!keep.log\n")];
+        assert!(ignored(&files, "a.log"));
+        assert!(!ignored(&files, "keep.log"));
+        let files = [file("", "!keep.log\n*.log\n")];
+        assert!(ignored(&files, "keep.log"));
+    }
+
+    #[test]
+    fn nested_files() {
+        let files = [file("", "*.log\n"), file("sub", "!keep.log\n/local\n")];
+        assert!(ignored(&files, "sub/a.log"));
+        assert!(!ignored(&files, "sub/keep.log"));
+        assert!(ignored(&files, "keep.log"));
+        // rules are relative to their ignore file
+        assert!(ignored(&files, "sub/local"));
+        assert!(!ignored(&files, "local"));
+        assert!(!ignored(&files, "sub/x/local"));
+    }
+}
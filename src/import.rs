@@ -0,0 +1,656 @@
+//! Translation of GNU Stow, chezmoi and yadm layouts into dot entries
+
+use crate::config::{Context, File, SyncMode};
+use crate::error::{DotError, Result};
+use crate::git;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    // stow directory, each sub directory is a package mirroring target
+    Stow,
+    // chezmoi source directory, attributes are encoded in names
+    Chezmoi,
+    // yadm repository, home is its work tree
+    Yadm,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Layout, String> {
+        match s {
+            "stow" => Ok(Layout::Stow),
+            "chezmoi" => Ok(Layout::Chezmoi),
+            "yadm" => Ok(Layout::Yadm),
+            _ => Err(format!(
+                "unknown layout {}, expected stow, chezmoi or yadm",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Layout::Stow => write!(f, "stow"),
+            Layout::Chezmoi => write!(f, "chezmoi"),
+            Layout::Yadm => write!(f, "yadm"),
+        }
+    }
+}
+
+// A file of an imported entry
+#[derive(Debug)]
+pub struct Content {
+    // path relative to entry, empty for file entries
+    pub path: PathBuf,
+    pub source: PathBuf,
+    pub perms: Option<u32>,
+}
+
+// An entry to add, with the files it is made of
+#[derive(Debug)]
+pub struct Import {
+    // system path of entry
+    pub target: PathBuf,
+    pub dir: bool,
+    pub mode: SyncMode,
+    pub files: Vec<Content>,
+    // entry settings to restore, from an export manifest
+    pub entry: Option<File>,
+}
+
+impl Import {
+    fn file<P: Into<PathBuf>>(target: PathBuf, source: P, perms: Option<u32>) -> Import {
+        Import {
+            target,
+            dir: false,
+            mode: SyncMode::Mirror,
+            files: vec![Content {
+                path: PathBuf::new(),
+                source: source.into(),
+                perms,
+            }],
+            entry: None,
+        }
+    }
+
+    // Write entry content at dst, symbolic links are copied as is
+    pub fn write(&self, dst: &Path) -> Result<()> {
+        if self.dir {
+            fs::create_dir_all(dst)?;
+        }
+        for content in &self.files {
+            let to = if content.path.as_os_str().is_empty() {
+                dst.to_owned()
+            } else {
+                dst.join(&content.path)
+            };
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let from = &content.source;
+            let res = if fs::symlink_metadata(from)?.file_type().is_symlink() {
+                fs::read_link(from).and_then(|link| symlink(link, &to))
+            } else {
+                fs::copy(from, &to).map(|_| ())
+            };
+            res.map_err(|e| DotError::wrap(to.display().to_string(), e))?;
+            if let Some(mode) = content.perms {
+                fs::set_permissions(&to, fs::Permissions::from_mode(mode))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Translation {
+    pub imports: Vec<Import>,
+    // foreign paths that cannot be translated, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+    // global excludes to add
+    pub exclude: Vec<String>,
+}
+
+impl Translation {
+    fn skip<S: Into<String>>(&mut self, path: &Path, reason: S) {
+        self.skipped.push((path.to_owned(), reason.into()));
+    }
+}
+
+// Directory entries sorted by name, for a stable output
+fn read_dir(dir: &Path) -> Result<Vec<fs::DirEntry>> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(|e| DotError::wrap(dir.display().to_string(), e))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    Ok(entries)
+}
+
+// Translate foreign layout at path, files are installed under target.
+// Yadm files are extracted into tmp first, it must outlive the translation.
+pub fn translate(
+    ctx: &Context,
+    layout: Layout,
+    path: &Path,
+    target: &Path,
+    tmp: &Path,
+) -> Result<Translation> {
+    if !path.exists() {
+        return Err(DotError::NotFound(path.display().to_string()).into());
+    }
+    let mut out = Translation::default();
+    match layout {
+        Layout::Stow => stow(ctx, path, target, &mut out)?,
+        Layout::Chezmoi => {
+            // source state can be moved to a sub directory
+            let root = match fs::read_to_string(path.join(".chezmoiroot")) {
+                Ok(sub) => path.join(sub.trim()),
+                Err(_) => path.to_owned(),
+            };
+            chezmoi_entries(ctx, &root, target, Attrs::default(), &mut out)?;
+            out.imports.sort_by(|a, b| a.target.cmp(&b.target));
+        }
+        Layout::Yadm => yadm(ctx, path, target, tmp, &mut out)?,
+    }
+    Ok(out)
+}
+
+// Manifest of an archive made by `dotto export`, relative to archive root
+pub const MANIFEST: &str = ".config/dotto/export.yml";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    pub files: Vec<Exported>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub exclude: Vec<String>,
+}
+
+// Exported entry, content is at archive path (glob base for glob entries)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Exported {
+    pub archive: PathBuf,
+    pub entry: File,
+}
+
+// Translate tree extracted from an archive made by `dotto export`, entries are
+// rebuilt from its manifest. Archives without one are laid out like a stow
+// package installed under target.
+pub fn archive(ctx: &Context, dir: &Path, target: &Path) -> Result<Translation> {
+    let mut out = Translation::default();
+    let path = dir.join(MANIFEST);
+    if !path.is_file() {
+        package(ctx, dir, target, true, false, &mut out)?;
+        return Ok(out);
+    }
+    let text =
+        fs::read_to_string(&path).map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+    let manifest: Manifest =
+        serde_yaml::from_str(&text).map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+    // manifest is not part of any entry
+    fs::remove_file(&path)?;
+    for exported in manifest.files {
+        let source = dir.join(&exported.archive);
+        let mut target = target.join(&exported.archive);
+        if source.symlink_metadata().is_err() {
+            out.skip(&target, "not in archive");
+            continue;
+        }
+        let mut import = if source.is_dir() {
+            let mut files = Vec::new();
+            package_files(&source, Path::new(""), false, &mut files)?;
+            // glob base may hold other entries, only matches belong to this one
+            let entry = &exported.entry;
+            if entry.is_glob() {
+                let tail = entry.path.strip_prefix(entry.glob_base())?;
+                let pattern = glob::Pattern::new(&tail.display().to_string())
+                    .map_err(|e| DotError::wrap(entry.path.display().to_string(), e))?;
+                files.retain(|c| c.path.ancestors().any(|a| pattern.matches_path(a)));
+                target = target.join(tail);
+            }
+            Import {
+                target,
+                dir: true,
+                mode: SyncMode::Mirror,
+                files,
+                entry: None,
+            }
+        } else {
+            Import::file(target, source, None)
+        };
+        import.mode = exported.entry.mode;
+        import.entry = Some(exported.entry);
+        out.imports.push(import);
+    }
+    out.exclude = manifest.exclude;
+    Ok(out)
+}
+
+// Version control and backup files stow never links
+fn stow_ignored(name: &str, top: bool) -> bool {
+    let vcs = [
+        ".git",
+        ".gitignore",
+        ".gitmodules",
+        ".hg",
+        ".svn",
+        "CVS",
+        "RCS",
+        "_darcs",
+    ];
+    let docs =
+        top && (name.starts_with("README") || name.starts_with("LICENSE") || name == "COPYING");
+    vcs.contains(&name)
+        || docs
+        || name.ends_with('~')
+        || name.starts_with(".#")
+        || (name.len() > 1 && name.starts_with('#') && name.ends_with('#'))
+}
+
+// `dot-` prefix of stow packages is translated as with `stow --dotfiles`
+fn package_name(name: &str, stow: bool) -> String {
+    match name.strip_prefix("dot-") {
+        Some(rest) if stow => format!(".{}", rest),
+        _ => name.to_owned(),
+    }
+}
+
+fn stow(ctx: &Context, dir: &Path, target: &Path, out: &mut Translation) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ".stowrc" {
+            out.skip(&path, "stow options are not supported");
+            continue;
+        }
+        if name.starts_with('.') {
+            continue;
+        }
+        if !entry.file_type()?.is_dir() {
+            out.skip(&path, "not in a package");
+            continue;
+        }
+        package(ctx, &path, target, true, true, out)?;
+    }
+    Ok(())
+}
+
+// Package items become entries, directories containing locations bases are
+// descended into so that e.g .config is not managed as a whole. Names are
+// translated and ignored files skipped only for stow packages.
+fn package(
+    ctx: &Context,
+    dir: &Path,
+    target: &Path,
+    top: bool,
+    stow: bool,
+    out: &mut Translation,
+) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if stow && top && name == ".stow-local-ignore" {
+            out.skip(&path, "stow ignore lists are not supported");
+            continue;
+        }
+        if stow && stow_ignored(&name, top) {
+            continue;
+        }
+        let target = target.join(package_name(&name, stow));
+        if !entry.file_type()?.is_dir() {
+            out.imports.push(Import::file(target, path, None));
+            continue;
+        }
+        if ctx
+            .bases()
+            .iter()
+            .any(|(_, base)| base.starts_with(&target))
+        {
+            package(ctx, &path, &target, false, stow, out)?;
+            continue;
+        }
+        let mut files = Vec::new();
+        package_files(&path, Path::new(""), stow, &mut files)?;
+        // files not in package are left alone on install, as stow does
+        out.imports.push(Import {
+            target,
+            dir: true,
+            mode: SyncMode::Additive,
+            files,
+            entry: None,
+        });
+    }
+    Ok(())
+}
+
+fn package_files(dir: &Path, rel: &Path, stow: bool, files: &mut Vec<Content>) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if stow && stow_ignored(&name, false) {
+            continue;
+        }
+        let rel = rel.join(package_name(&name, stow));
+        if entry.file_type()?.is_dir() {
+            package_files(&entry.path(), &rel, stow, files)?;
+        } else {
+            files.push(Content {
+                path: rel,
+                source: entry.path(),
+                perms: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+// chezmoi attributes inherited by directory content
+#[derive(Clone, Copy, Debug, Default)]
+struct Attrs {
+    private: bool,
+    readonly: bool,
+    executable: bool,
+    exact: bool,
+    // names are not parsed under external directories
+    literal: bool,
+}
+
+impl Attrs {
+    fn perms(&self) -> Option<u32> {
+        if !(self.private || self.readonly || self.executable) {
+            return None;
+        }
+        let mut mode = if self.executable { 0o755 } else { 0o644 };
+        if self.private {
+            mode &= 0o700;
+        }
+        if self.readonly {
+            mode &= !0o222;
+        }
+        Some(mode)
+    }
+}
+
+// Attributes of a source name, unsupported ones are returned as error
+fn chezmoi_name(
+    name: &str,
+    dir: bool,
+    inherited: Attrs,
+) -> std::result::Result<(String, Attrs), String> {
+    let mut attrs = Attrs {
+        executable: false,
+        ..inherited
+    };
+    if attrs.literal {
+        return Ok((name.to_owned(), attrs));
+    }
+    let unsupported: &[&str] = if dir {
+        &["remove_"]
+    } else {
+        &[
+            "after_",
+            "before_",
+            "create_",
+            "encrypted_",
+            "modify_",
+            "once_",
+            "onchange_",
+            "remove_",
+            "run_",
+            "symlink_",
+        ]
+    };
+    let mut rest = name;
+    loop {
+        if let Some(p) = unsupported.iter().find(|p| rest.starts_with(*p)) {
+            return Err(format!(
+                "{} attribute is not supported",
+                p.trim_end_matches('_')
+            ));
+        }
+        let (attr, r) = match rest.find('_') {
+            Some(i) => (&rest[..i + 1], &rest[i + 1..]),
+            None => break,
+        };
+        match (attr, dir) {
+            ("private_", _) => attrs.private = true,
+            ("readonly_", _) => attrs.readonly = true,
+            ("empty_", false) => {}
+            ("executable_", false) => attrs.executable = true,
+            ("exact_", true) => attrs.exact = true,
+            ("external_", true) => attrs.literal = true,
+            ("literal_", _) => {
+                rest = r;
+                break;
+            }
+            ("dot_", _) => {
+                return chezmoi_suffix(&format!(".{}", r), dir).map(|n| (n, attrs));
+            }
+            _ => break,
+        }
+        rest = r;
+    }
+    chezmoi_suffix(rest, dir).map(|n| (n, attrs))
+}
+
+fn chezmoi_suffix(name: &str, dir: bool) -> std::result::Result<String, String> {
+    if dir {
+        return Ok(name.to_owned());
+    }
+    if name.ends_with(".tmpl") {
+        return Err("templates are not supported".to_owned());
+    }
+    Ok(name.trim_end_matches(".literal").to_owned())
+}
+
+// Source name parsed, or None when the item is skipped
+fn chezmoi_item(
+    entry: &fs::DirEntry,
+    attrs: Attrs,
+    out: &mut Translation,
+) -> Result<Option<(String, Attrs, bool)>> {
+    let path = entry.path();
+    let name = match entry.file_name().to_str() {
+        Some(n) => n.to_owned(),
+        None => {
+            out.skip(&path, "name is not valid unicode");
+            return Ok(None);
+        }
+    };
+    if !attrs.literal && name.starts_with(".chezmoi") {
+        out.skip(&path, "chezmoi special files are not supported");
+        return Ok(None);
+    }
+    // chezmoi ignores other hidden source files (e.g .git)
+    if !attrs.literal && name.starts_with('.') {
+        return Ok(None);
+    }
+    let is_dir = entry.file_type()?.is_dir();
+    match chezmoi_name(&name, is_dir, attrs) {
+        Ok((name, sub)) => Ok(Some((name, sub, is_dir))),
+        Err(reason) => {
+            out.skip(&path, reason);
+            Ok(None)
+        }
+    }
+}
+
+// Source items become entries as stow package items do. Directories are
+// descended into when they contain locations bases or exact directories,
+// exact directories are entries of their own synced in mirror mode.
+fn chezmoi_entries(
+    ctx: &Context,
+    dir: &Path,
+    target: &Path,
+    attrs: Attrs,
+    out: &mut Translation,
+) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry.path();
+        let (name, sub, is_dir) = match chezmoi_item(&entry, attrs, out)? {
+            Some(item) => item,
+            None => continue,
+        };
+        let target = target.join(name);
+        if !is_dir {
+            out.imports.push(Import::file(target, path, sub.perms()));
+            continue;
+        }
+        let bases = ctx
+            .bases()
+            .iter()
+            .any(|(_, base)| base.starts_with(&target));
+        if bases || (!sub.exact && has_exact(&path, sub)) {
+            chezmoi_entries(ctx, &path, &target, sub, out)?;
+            continue;
+        }
+        let mut files = Vec::new();
+        chezmoi(&path, Path::new(""), sub, out, &mut files)?;
+        // files not in source are left alone unless directory is exact
+        let mode = if sub.exact {
+            SyncMode::Mirror
+        } else {
+            SyncMode::Additive
+        };
+        out.imports.push(Import {
+            target,
+            dir: true,
+            mode,
+            files,
+            entry: None,
+        });
+    }
+    Ok(())
+}
+
+// Whether an exact directory is nested in source directory
+fn has_exact(dir: &Path, attrs: Attrs) -> bool {
+    let entries = match read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return false,
+    };
+    entries.iter().any(|entry| {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        if !is_dir || (!attrs.literal && name.starts_with('.')) {
+            return false;
+        }
+        match chezmoi_name(&name, true, attrs) {
+            Ok((_, sub)) => sub.exact || has_exact(&entry.path(), sub),
+            Err(_) => false,
+        }
+    })
+}
+
+// Files of source directory, relative to entry
+fn chezmoi(
+    dir: &Path,
+    rel: &Path,
+    attrs: Attrs,
+    out: &mut Translation,
+    files: &mut Vec<Content>,
+) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry.path();
+        let (name, sub, is_dir) = match chezmoi_item(&entry, attrs, out)? {
+            Some(item) => item,
+            None => continue,
+        };
+        if is_dir {
+            chezmoi(&path, &rel.join(name), sub, out, files)?;
+        } else {
+            files.push(Content {
+                path: rel.join(name),
+                source: path,
+                perms: sub.perms(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn walk(dir: &Path, rel: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let rel = rel.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), &rel, files)?;
+        } else {
+            files.push(rel);
+        }
+    }
+    Ok(())
+}
+
+// Path with `##` alternates resolved, only `##default` can be translated.
+// Returns whether an alternate was resolved.
+fn yadm_path(path: &Path) -> std::result::Result<(PathBuf, bool), String> {
+    let mut out = PathBuf::new();
+    let mut alternate = false;
+    for part in path.iter() {
+        let part = part.to_string_lossy();
+        match part.split_once("##") {
+            None => out.push(part.as_ref()),
+            Some((name, "default")) | Some((name, "")) => {
+                out.push(name);
+                alternate = true;
+            }
+            Some((_, cond)) if cond == "t" || cond.starts_with("template") => {
+                return Err("templates are not supported".to_owned())
+            }
+            Some((_, cond)) => return Err(format!("alternate ##{} is not supported", cond)),
+        }
+    }
+    Ok((out, alternate))
+}
+
+// Tracked files of yadm repository, extracted from HEAD
+fn yadm(
+    ctx: &Context,
+    repo: &Path,
+    target: &Path,
+    tmp: &Path,
+    out: &mut Translation,
+) -> Result<()> {
+    if !git::checkout_to(ctx, repo, "HEAD", Path::new("."), tmp)? {
+        return Ok(());
+    }
+    let mut tracked = Vec::new();
+    walk(tmp, Path::new(""), &mut tracked)?;
+    let mut alternates = Vec::new();
+    for rel in tracked {
+        if rel.starts_with(".config/yadm") || rel.starts_with(".yadm") {
+            out.skip(&rel, "yadm configuration is not supported");
+            continue;
+        }
+        if rel.starts_with(".local/share/yadm") {
+            out.skip(&rel, "yadm encrypted archive is not supported");
+            continue;
+        }
+        match yadm_path(&rel) {
+            Ok((path, true)) => alternates.push((rel, path)),
+            Ok((path, false)) => {
+                out.imports
+                    .push(Import::file(target.join(path), tmp.join(&rel), None))
+            }
+            Err(reason) => out.skip(&rel, reason),
+        }
+    }
+    // a plain file wins over its default alternate
+    for (rel, path) in alternates {
+        let target = target.join(path);
+        if out.imports.iter().any(|i| i.target == target) {
+            out.skip(&rel, "a plain file is tracked for it");
+            continue;
+        }
+        out.imports.push(Import::file(target, tmp.join(&rel), None));
+    }
+    out.imports.sort_by(|a, b| a.target.cmp(&b.target));
+    Ok(())
+}
@@ -1,40 +1,109 @@
+mod check;
 mod cmd;
 mod config;
 mod error;
-mod storage;
+mod git;
+mod history;
+mod ignore;
+mod import;
+mod plan;
+mod privilege;
+mod report;
 mod sync;
+mod trash;
+mod util;
 
 use crate::cmd::*;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
-// TODO: clean command to remove deleted projects / delete command
-// TODO: add status command to get all diff and all
-
 #[derive(Debug, StructOpt)]
 enum CliCommand {
     /// Add file(s) to dot index
     #[structopt(name = "add")]
     Add(AddCmd),
+    /// Suggest well known dotfiles to manage and add the selected ones
+    #[structopt(name = "adopt")]
+    Adopt(AdoptCmd),
+    /// Apply a plan saved with --plan-out, refused if anything changed since
+    ///
+    /// Exit codes: 0 applied, 1 failed, 2 nothing to do, 3 cancelled, 4 planned (--dry-run)
+    #[structopt(name = "apply")]
+    Apply(ApplyCmd),
+    /// Apply privileged operations read from stdin (internal)
+    #[structopt(
+        name = "apply-privileged",
+        raw(setting = "structopt::clap::AppSettings::Hidden")
+    )]
+    ApplyPrivileged(ApplyPrivilegedCmd),
+    /// Validate dot configuration
+    #[structopt(name = "check")]
+    Check(CheckCmd),
+    /// Inspect dot configuration
+    #[structopt(name = "config")]
+    Config(ConfigCmd),
     /// Open config in your editor ($EDITOR by default)
     #[structopt(name = "edit")]
     Edit(EditCmd),
     /// Exclude files (glob style: e.g src/**/*.rs) from install and update
     #[structopt(name = "exclude")]
     Exclude(ExcludeCmd),
+    /// Export installed files as a tar archive or a GNU Stow package
+    #[structopt(name = "export")]
+    Export(ExportCmd),
     /// Run git in dot context
     #[structopt(
         name = "git",
         raw(setting = "structopt::clap::AppSettings::AllowExternalSubcommands")
     )]
     Git(GitCmd),
+    /// List recorded install and update runs
+    #[structopt(name = "history")]
+    History(HistoryCmd),
+    /// Import dotfiles from GNU Stow, chezmoi or yadm
+    #[structopt(name = "import")]
+    Import(ImportCmd),
+    /// Import a tar archive made by `dotto export`
+    #[structopt(name = "import-archive")]
+    ImportArchive(ImportArchiveCmd),
+    /// Only sync files matching patterns (glob style, `!` negates, last match wins)
+    #[structopt(name = "include")]
+    Include(IncludeCmd),
     /// Initialize dot configuration and folder
     #[structopt(name = "init")]
     Init(InitCmd),
     /// Install config files (warning: can delete files on system)
+    ///
+    /// Exit codes: 0 applied, 1 failed, 2 nothing to do, 3 cancelled, 4 planned (--dry-run)
     #[structopt(name = "install")]
     Install(InstallCmd),
+    /// Show git history of a managed system path
+    #[structopt(name = "log")]
+    Log(LogCmd),
+    /// Move a managed file on system and in dot directory
+    #[structopt(name = "mv")]
+    Mv(MvCmd),
+    /// Move dot directory (and its git dir) to a new place
+    #[structopt(name = "relocate")]
+    Relocate(RelocateCmd),
+    /// Sync dot repository with a remote (e.g push after update, pull before install)
+    #[structopt(name = "remote")]
+    Remote(RemoteCmd),
+    /// Install a managed system path as it was at a given revision
+    #[structopt(name = "restore")]
+    Restore(RestoreCmd),
+    /// Stop managing file(s)
+    #[structopt(name = "rm")]
+    Rm(RmCmd),
+    /// Show differences between system and dot directory
+    #[structopt(name = "status")]
+    Status(StatusCmd),
+    /// Revert last install or update, or a run from `dotto history`
+    #[structopt(name = "undo")]
+    Undo(UndoCmd),
     /// Update dot directory with new changes
+    ///
+    /// Exit codes: 0 applied, 1 failed, 2 nothing to do, 3 cancelled, 4 planned (--dry-run)
     #[structopt(name = "update")]
     Update(UpdateCmd),
 }
@@ -46,6 +115,15 @@ struct Cli {
     /// Path to dot config file, use $DOT_PATH/config.yml by default
     #[structopt(name = "config", short = "c", long = "config", parse(from_os_str))]
     config: Option<PathBuf>,
+    /// Use DIR as home, XDG and dot directories default under it
+    #[structopt(long = "home", parse(from_os_str))]
+    home: Option<PathBuf>,
+    /// Path to dot directory, use $DOT_PATH, `dot` from config or ~/.dot by default
+    #[structopt(long = "dot-path", parse(from_os_str))]
+    dot_path: Option<PathBuf>,
+    /// Re-root every system location under DIR (e.g to preview an install)
+    #[structopt(long = "root", parse(from_os_str))]
+    root: Option<PathBuf>,
 
     #[structopt(subcommand)]
     command: CliCommand,
@@ -53,11 +131,30 @@ struct Cli {
 
 fn main() -> error::Result<()> {
     let args = Cli::from_args();
+    // privileged helper must not depend on user environment or config
+    if let CliCommand::ApplyPrivileged(a) = &args.command {
+        return a.run();
+    }
     let mut context = config::Context::new();
+    if let Some(home) = &args.home {
+        context.set_home(home)?;
+    }
+    if let Some(dot) = &args.dot_path {
+        context.set_dot(dot)?;
+    }
     if let Some(path) = &args.config {
         context.dot_config = path.to_owned();
     }
-    let mut config = match config::Config::open(&context.dot_config) {
+    // dot directory precedence: --dot-path, $DOT_PATH, `dot` in config, ~/.dot
+    if args.dot_path.is_none() && std::env::var_os("DOT_PATH").is_none() {
+        if let Some(dot) = config::Config::read_dot(&context)? {
+            context.set_dot(dot)?;
+            if let Some(path) = &args.config {
+                context.dot_config = path.to_owned();
+            }
+        }
+    }
+    let mut config = match config::Config::open(&context) {
         Ok(c) => c,
         Err(e) => {
             if let CliCommand::Init(_) = &args.command {
@@ -67,15 +164,44 @@ fn main() -> error::Result<()> {
             }
         }
     };
+    context.bare = config.git().map(|g| g.bare).unwrap_or(false);
+    // config is always read from real paths, only system locations are re-rooted
+    if let Some(root) = &args.root {
+        context.set_root(root)?;
+    }
+    context.set_locations(&config.locations)?;
+    // install, update and checks report their outcome through exit code
+    let mut code = 0;
     match args.command {
         CliCommand::Add(a) => a.run(&context, &mut config)?,
+        CliCommand::Adopt(a) => a.run(&context, &mut config)?,
+        CliCommand::Apply(a) => code = a.run(&context, &config).code(),
+        CliCommand::ApplyPrivileged(_) => unreachable!(),
+        CliCommand::Check(c) => code = c.run(&context, &mut config)?.code(),
+        CliCommand::Config(c) => c.run(&context, &mut config)?,
         CliCommand::Edit(e) => e.run(&context, &mut config)?,
         CliCommand::Exclude(e) => e.run(&context, &mut config)?,
+        CliCommand::Export(e) => e.run(&context, &mut config)?,
         CliCommand::Git(g) => g.run(&context, &mut config)?,
+        CliCommand::History(h) => h.run(&context, &mut config)?,
+        CliCommand::Import(i) => i.run(&context, &mut config)?,
+        CliCommand::ImportArchive(i) => i.run(&context, &mut config)?,
+        CliCommand::Include(i) => i.run(&context, &mut config)?,
         CliCommand::Init(i) => i.run(&context, &mut config)?,
-        CliCommand::Install(i) => i.run(&context, &mut config)?,
-        CliCommand::Update(u) => u.run(&context, &mut config)?,
+        CliCommand::Install(i) => code = i.run(&context, &config).code(),
+        CliCommand::Log(l) => l.run(&context, &mut config)?,
+        CliCommand::Mv(m) => m.run(&context, &mut config)?,
+        CliCommand::Relocate(r) => code = r.run(&context, &mut config)?.code(),
+        CliCommand::Remote(r) => r.run(&context, &mut config)?,
+        CliCommand::Restore(r) => r.run(&context, &mut config)?,
+        CliCommand::Rm(r) => r.run(&context, &mut config)?,
+        CliCommand::Status(s) => s.run(&context, &mut config)?,
+        CliCommand::Undo(u) => u.run(&context, &mut config)?,
+        CliCommand::Update(u) => code = u.run(&context, &config).code(),
     }
     config.save(&context.dot_config)?;
+    if code != 0 {
+        std::process::exit(code);
+    }
     Ok(())
 }
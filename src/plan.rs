@@ -0,0 +1,169 @@
+//! Saved plans: a reviewed plan is applied only if nothing changed since it was made
+
+use crate::cmd::{roots, Direction, SyncContext};
+use crate::config::{Context, Location, SyncMode};
+use crate::error::{DotError, Result};
+use crate::sync::Diff;
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// State of a path when plan was made
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum State {
+    Missing,
+    Dir,
+    File { md5: String },
+}
+
+impl State {
+    pub fn new(path: &Path) -> Result<State> {
+        if !path.exists() {
+            return Ok(State::Missing);
+        }
+        if path.is_dir() {
+            return Ok(State::Dir);
+        }
+        let content = fs::read(path).map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        Ok(State::File {
+            md5: format!("{:x}", md5::compute(content)),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedDiff {
+    #[serde(flatten)]
+    pub diff: Diff,
+    pub src: State,
+    pub dst: State,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedEntry {
+    pub location: Location,
+    pub path: PathBuf,
+    // roots as resolved when plan was made
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    #[serde(default)]
+    pub mode: SyncMode,
+    pub diffs: Vec<SavedDiff>,
+    // listed for review, never applied
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub skipped: Vec<Diff>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Plan {
+    pub direction: Direction,
+    pub entries: Vec<SavedEntry>,
+}
+
+fn outdated<S: Into<String>>(reason: S) -> failure::Error {
+    DotError::PlanChanged(reason.into()).into()
+}
+
+// Sorted diffs, sync_diff order is not stable
+fn sorted(diffs: &[Diff]) -> Vec<(PathBuf, String)> {
+    let mut diffs = diffs
+        .iter()
+        .map(|d| (d.path().to_owned(), d.kind().to_string()))
+        .collect::<Vec<_>>();
+    diffs.sort();
+    diffs
+}
+
+impl Plan {
+    // Snapshot both sides of every diff
+    pub fn new(ctx: &Context, direction: Direction, sync_ctx: &[SyncContext]) -> Result<Plan> {
+        let mut entries = Vec::new();
+        for sctx in sync_ctx {
+            let (src, dst) = roots(ctx, &sctx.location, &sctx.path, direction)?;
+            let mut diffs = Vec::new();
+            for diff in &sctx.diffs {
+                diffs.push(SavedDiff {
+                    diff: diff.clone(),
+                    src: State::new(&diff.full_path(&src))?,
+                    dst: State::new(&diff.full_path(&dst))?,
+                });
+            }
+            entries.push(SavedEntry {
+                location: sctx.location.clone(),
+                path: sctx.path.clone(),
+                src,
+                dst,
+                mode: sctx.mode,
+                diffs,
+                skipped: sctx.skipped.clone(),
+            });
+        }
+        Ok(Plan { direction, entries })
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Plan> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        let plan = serde_yaml::from_str(&text)
+            .map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        Ok(plan)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file =
+            fs::File::create(path).map_err(|e| DotError::wrap(path.display().to_string(), e))?;
+        serde_yaml::to_writer(file, self).map_err(failure::Error::from)
+    }
+
+    // Check plan against current state, fresh is the plan computed now in mirror mode.
+    // Returns the saved plan if both sides are unchanged.
+    pub fn verify(&self, ctx: &Context, fresh: &[SyncContext]) -> Result<Vec<SyncContext>> {
+        let mut sync_ctx = Vec::new();
+        for entry in &self.entries {
+            let (src, dst) = roots(ctx, &entry.location, &entry.path, self.direction)?;
+            if src != entry.src || dst != entry.dst {
+                return Err(outdated(format!(
+                    "{} resolves to other paths",
+                    entry.path.display()
+                )));
+            }
+            for saved in &entry.diffs {
+                let src_path = saved.diff.full_path(&src);
+                let dst_path = saved.diff.full_path(&dst);
+                if State::new(&src_path)? != saved.src {
+                    return Err(outdated(format!("{} changed", src_path.display())));
+                }
+                if State::new(&dst_path)? != saved.dst {
+                    return Err(outdated(format!("{} changed", dst_path.display())));
+                }
+            }
+            let diffs = entry
+                .diffs
+                .iter()
+                .map(|d| d.diff.clone())
+                .collect::<Vec<_>>();
+            let mut sctx = SyncContext::new(entry.location.clone(), entry.path.clone(), diffs);
+            sctx.mode = entry.mode;
+            sctx.skipped = entry.skipped.clone();
+            sync_ctx.push(sctx);
+        }
+        // something not covered by saved diffs may have changed too
+        let same = sync_ctx.len() == fresh.len()
+            && sync_ctx.iter().all(|saved| {
+                let mut all = saved.diffs.clone();
+                all.extend(saved.skipped.iter().cloned());
+                fresh.iter().any(|f| {
+                    f.location == saved.location
+                        && f.path == saved.path
+                        && sorted(&f.diffs) == sorted(&all)
+                })
+            });
+        if !same {
+            return Err(outdated("changes differ from the saved plan"));
+        }
+        Ok(sync_ctx)
+    }
+}
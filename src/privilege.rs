@@ -0,0 +1,202 @@
+//! Applies diffs that need elevated privileges through an external escalation command
+
+use crate::error::{DotError, Result};
+use crate::sync::{Diff, DiffKind};
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+pub const DEFAULT_ESCALATE: &str = "sudo";
+
+// A single filesystem operation, this is the only thing the privileged helper accepts
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Mkdir { path: PathBuf },
+    Copy { src: PathBuf, dst: PathBuf },
+    Remove { path: PathBuf },
+}
+
+fn writable<P: AsRef<Path>>(path: P) -> bool {
+    let path = match CString::new(path.as_ref().as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+// Returns first existing ancestor of path (path included)
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|p| p.symlink_metadata().is_ok())
+}
+
+// Check if applying diff into dst root requires elevated privileges
+pub fn needs_privilege<P: AsRef<Path>>(dst_root: P, diff: &Diff) -> bool {
+    let path = diff.full_path(dst_root);
+    let target = match diff.kind() {
+        DiffKind::Modified if path.exists() => Some(path.as_path()),
+        DiffKind::Added | DiffKind::Modified => path.parent().and_then(existing_ancestor),
+        DiffKind::Deleted => path.parent(),
+    };
+    match target {
+        Some(t) => !writable(t),
+        None => false,
+    }
+}
+
+// Check if applying operation requires elevated privileges
+pub fn operation_needs_privilege(op: &Operation) -> bool {
+    let target = match op {
+        Operation::Mkdir { path } => existing_ancestor(path),
+        Operation::Copy { dst, .. } if dst.exists() => Some(dst.as_path()),
+        Operation::Copy { dst, .. } => dst.parent().and_then(existing_ancestor),
+        Operation::Remove { path } => path.parent(),
+    };
+    match target {
+        Some(t) => !writable(t),
+        None => false,
+    }
+}
+
+// Split diffs into (unprivileged, privileged) while keeping their order
+pub fn split<P: AsRef<Path>>(dst_root: P, diffs: &[Diff]) -> (Vec<Diff>, Vec<Diff>) {
+    let dst_root = dst_root.as_ref();
+    diffs
+        .iter()
+        .cloned()
+        .partition(|d| !needs_privilege(dst_root, d))
+}
+
+// Translate diffs into operations, mirrors sync::sync
+pub fn operations<A, B>(src: A, dst: B, diffs: &[Diff]) -> Vec<Operation>
+where
+    A: AsRef<Path>,
+    B: AsRef<Path>,
+{
+    let src = src.as_ref();
+    let dst = dst.as_ref();
+    let mut ops = Vec::new();
+    if src.is_dir() {
+        ops.push(Operation::Mkdir {
+            path: dst.to_owned(),
+        });
+    } else if let Some(parent) = dst.parent() {
+        ops.push(Operation::Mkdir {
+            path: parent.to_owned(),
+        });
+    }
+    for diff in diffs {
+        let src_path = diff.full_path(src);
+        let dst_path = diff.full_path(dst);
+        match diff.kind() {
+            DiffKind::Added if src_path.is_dir() => ops.push(Operation::Mkdir { path: dst_path }),
+            DiffKind::Added | DiffKind::Modified => ops.push(Operation::Copy {
+                src: src_path,
+                dst: dst_path,
+            }),
+            DiffKind::Deleted => ops.push(Operation::Remove { path: dst_path }),
+        }
+    }
+    ops
+}
+
+fn validate_path(path: &Path) -> Result<()> {
+    if !path.is_absolute() {
+        return Err(
+            DotError::InvalidOperation(format!("{} is not absolute", path.display())).into(),
+        );
+    }
+    let clean = path
+        .components()
+        .all(|c| matches!(c, Component::RootDir | Component::Normal(_)));
+    if !clean {
+        return Err(
+            DotError::InvalidOperation(format!("{} is not normalized", path.display())).into(),
+        );
+    }
+    Ok(())
+}
+
+// Validate all operations before applying any of them
+pub fn validate(ops: &[Operation]) -> Result<()> {
+    for op in ops {
+        match op {
+            Operation::Mkdir { path } => validate_path(path)?,
+            Operation::Copy { src, dst } => {
+                validate_path(src)?;
+                validate_path(dst)?;
+                let meta = src
+                    .symlink_metadata()
+                    .map_err(|e| DotError::wrap(src.display().to_string(), e))?;
+                if !meta.is_file() {
+                    return Err(DotError::InvalidOperation(format!(
+                        "{} is not a regular file",
+                        src.display()
+                    ))
+                    .into());
+                }
+            }
+            Operation::Remove { path } => {
+                validate_path(path)?;
+                if path.parent().is_none() {
+                    return Err(DotError::InvalidOperation("cannot remove /".to_string()).into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn apply(ops: &[Operation]) -> Result<()> {
+    for op in ops {
+        match op {
+            Operation::Mkdir { path } => fs::create_dir_all(path)?,
+            Operation::Copy { src, dst } => {
+                fs::copy(src, dst)?;
+            }
+            Operation::Remove { path } => {
+                let meta = path.symlink_metadata()?;
+                if meta.is_dir() {
+                    fs::remove_dir(path)?;
+                } else {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Run `dotto apply-privileged` through escalation command, operations are sent on stdin
+pub fn escalate(command: &str, ops: &[Operation]) -> Result<()> {
+    validate(ops)?;
+    let mut args = command.split_whitespace();
+    let program = args.next().unwrap_or(DEFAULT_ESCALATE);
+    let mut proc = process::Command::new(program)
+        .args(args)
+        .arg(env::current_exe()?)
+        .arg("apply-privileged")
+        .stdin(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| DotError::wrap(program, e))?;
+    serde_yaml::to_writer(proc.stdin.as_mut().unwrap(), ops)?;
+    // close stdin to let helper read until EOF
+    drop(proc.stdin.take());
+    if !proc.wait()?.success() {
+        return Err(DotError::PrivilegedFailed(command.to_string()).into());
+    }
+    Ok(())
+}
+
+// Read operations from stdin, this is the entry point of privileged helper
+pub fn read_operations() -> Result<Vec<Operation>> {
+    let stdin = io::stdin();
+    let ops: Vec<Operation> = serde_yaml::from_reader(stdin.lock())?;
+    validate(&ops)?;
+    Ok(ops)
+}
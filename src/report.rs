@@ -0,0 +1,111 @@
+//! Outcome and machine readable report of install and update
+
+use crate::cmd::Direction;
+use crate::config::Location;
+use crate::sync::DiffKind;
+use serde_derive::Serialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format {}, expected text or json", s)),
+        }
+    }
+}
+
+// Exit codes are stable, scripts can rely on them
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    // changes have been applied
+    Applied,
+    // an error occured, changes may be partially applied
+    Failed,
+    // system and dot directory are already in sync
+    NothingToDo,
+    // changes have been refused at confirmation
+    Cancelled,
+    // changes have been planned but not applied (--dry-run)
+    Planned,
+}
+
+impl Outcome {
+    pub fn code(self) -> i32 {
+        match self {
+            Outcome::Applied => 0,
+            Outcome::Failed => 1,
+            Outcome::NothingToDo => 2,
+            Outcome::Cancelled => 3,
+            Outcome::Planned => 4,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportDiff {
+    pub kind: DiffKind,
+    pub path: PathBuf,
+    #[serde(skip_serializing_if = "is_false")]
+    pub privileged: bool,
+    // suppressed by sync mode, not applied
+    #[serde(skip_serializing_if = "is_false")]
+    pub skipped: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportEntry {
+    pub location: Location,
+    pub path: PathBuf,
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    pub diffs: Vec<ReportDiff>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    #[serde(skip)]
+    pub direction: Direction,
+    pub command: &'static str,
+    pub status: Outcome,
+    pub code: i32,
+    pub plan: Vec<ReportEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+impl Report {
+    pub fn new(direction: Direction) -> Report {
+        Report {
+            direction,
+            command: match direction {
+                Direction::Install => "install",
+                Direction::Update => "update",
+            },
+            status: Outcome::NothingToDo,
+            code: Outcome::NothingToDo.code(),
+            plan: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn finish(&mut self, outcome: Outcome) {
+        self.status = outcome;
+        self.code = outcome.code();
+    }
+}
@@ -1,13 +1,22 @@
 //! Implements one way synchronisation
 
 use crate::error::{DotError, Result};
+use crate::ignore::{self, IgnoreFile};
+use crate::trash::Trash;
+use serde_derive::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::env;
 use std::fmt;
 use std::fs::{self, DirEntry, File};
 use std::io::{self, Read};
-use std::path::{Path, PathBuf};
+use std::os::unix::fs::{symlink, DirBuilderExt};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DiffKind {
     Added,
     // only for file
@@ -25,7 +34,7 @@ impl fmt::Display for DiffKind {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Diff {
     path: PathBuf,
     kind: DiffKind,
@@ -47,6 +56,15 @@ impl Diff {
     pub fn kind(&self) -> &DiffKind {
         &self.kind
     }
+
+    // Returns diff path joined to root, root itself is returned for root diff
+    pub fn full_path<P: AsRef<Path>>(&self, root: P) -> PathBuf {
+        let root = root.as_ref();
+        if self.path.parent().is_some() {
+            return root.join(&self.path);
+        }
+        root.to_owned()
+    }
 }
 
 impl fmt::Display for Diff {
@@ -65,23 +83,31 @@ enum FileType {
     None,
 }
 
+fn is_link(p: &Path) -> bool {
+    p.symlink_metadata()
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false)
+}
+
 impl FileType {
-    fn new<P: AsRef<Path>>(p: P) -> FileType {
+    // Symlinks are followed, unless they are synced as links
+    fn new<P: AsRef<Path>>(p: P, links: bool) -> FileType {
         let p = p.as_ref();
+        if links && is_link(p) {
+            return FileType::File;
+        }
         if !p.exists() {
             return FileType::None;
         }
-        match p.is_dir() {
-            true => FileType::Dir,
-            false => FileType::File,
+        if p.is_dir() {
+            FileType::Dir
+        } else {
+            FileType::File
         }
     }
 
     fn exists(&self) -> bool {
-        match self {
-            FileType::None => false,
-            _ => true,
-        }
+        !matches!(self, FileType::None)
     }
 }
 
@@ -90,6 +116,14 @@ pub struct SyncSettings {
     pub depth: usize,
     pub recursive: bool,
     pub exclude: Vec<glob::Pattern>,
+    // excludes shared by all entries, matched against every path component too
+    pub global: Vec<glob::Pattern>,
+    // include rules in order, true if rule is negated
+    pub include: Vec<(bool, glob::Pattern)>,
+    // path prepended to diffs paths when matching excludes
+    pub prefix: PathBuf,
+    // symlinks are compared and copied as links instead of being followed
+    pub links: bool,
 }
 
 impl SyncSettings {
@@ -105,34 +139,162 @@ impl SyncSettings {
                 .iter()
                 .map(|s| glob::Pattern::new(s).map_err(|e| DotError::wrap(s, e)))
                 .collect::<std::result::Result<Vec<glob::Pattern>, _>>()?,
+            global: Vec::new(),
+            include: Vec::new(),
+            prefix: PathBuf::new(),
+            links: false,
         })
     }
+
+    pub fn global(mut self, exclude: &[String]) -> Result<SyncSettings> {
+        for pattern in exclude {
+            self.global
+                .push(glob::Pattern::new(pattern).map_err(|e| DotError::wrap(pattern, e))?);
+        }
+        Ok(self)
+    }
+
+    pub fn include(mut self, include: &[String]) -> Result<SyncSettings> {
+        for rule in include {
+            let (negated, pattern) = match rule.strip_prefix('!') {
+                Some(p) => (true, p),
+                None => (false, rule.as_str()),
+            };
+            let pattern = glob::Pattern::new(pattern).map_err(|e| DotError::wrap(rule, e))?;
+            self.include.push((negated, pattern));
+        }
+        Ok(self)
+    }
+
+    pub fn prefix<P: Into<PathBuf>>(mut self, prefix: P) -> SyncSettings {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn links(mut self) -> SyncSettings {
+        self.links = true;
+        self
+    }
+
+    // Check if relative path is excluded, a path is excluded if any of its parents is
+    pub fn is_excluded<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = self.prefix.join(path.as_ref());
+        let global = self.global.iter().any(|g| {
+            path.iter().any(|c| g.matches_path(Path::new(c)))
+                || path.ancestors().any(|a| g.matches_path(a))
+        });
+        global
+            || self
+                .exclude
+                .iter()
+                .any(|e| path.ancestors().any(|a| e.matches_path(a)))
+    }
+
+    // Check if relative path is included, last matching rule wins.
+    // A rule matching a directory applies to everything inside it.
+    pub fn is_included<P: AsRef<Path>>(&self, path: P) -> bool {
+        if self.include.is_empty() {
+            return true;
+        }
+        let path = self.prefix.join(path.as_ref());
+        let mut included = false;
+        for (negated, pattern) in &self.include {
+            if path
+                .ancestors()
+                .filter(|a| !a.as_os_str().is_empty())
+                .any(|a| pattern.matches_path(a))
+            {
+                included = !negated;
+            }
+        }
+        included
+    }
+
+    // Filter diffs with include and exclude rules.
+    // Directories are kept when something inside them is kept, deleted
+    // directories are kept only if everything inside them is.
+    fn filter(&self, diffs: Vec<Diff>) -> Vec<Diff> {
+        let selected = diffs
+            .iter()
+            .map(|d| !self.is_excluded(&d.path) && self.is_included(&d.path))
+            .collect::<Vec<_>>();
+        let keep = diffs
+            .iter()
+            .map(|d| {
+                if d.path.as_os_str().is_empty() {
+                    return true;
+                }
+                if self.is_excluded(&d.path) {
+                    return false;
+                }
+                let inner = diffs
+                    .iter()
+                    .zip(selected.iter())
+                    .filter(|(o, _)| o.path != d.path && o.path.starts_with(&d.path))
+                    .map(|(_, s)| *s)
+                    .collect::<Vec<_>>();
+                if inner.is_empty() {
+                    return self.is_included(&d.path);
+                }
+                let any = inner.iter().any(|s| *s);
+                match d.kind {
+                    DiffKind::Deleted => any && inner.iter().all(|s| *s),
+                    _ => any || self.is_included(&d.path),
+                }
+            })
+            .collect::<Vec<_>>();
+        diffs
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, k)| *k)
+            .map(|(d, _)| d)
+            .collect()
+    }
 }
 
 #[derive(Clone, Debug)]
 struct SyncContext<'a> {
     current_depth: usize,
     settings: &'a SyncSettings,
+    // ignore files found from root to current directory
+    ignores: Vec<Rc<IgnoreFile>>,
 }
 
-impl SyncContext<'_> {
-    fn deeper(&self) -> SyncContext<'_> {
+impl<'a> SyncContext<'a> {
+    // Enter directory file, loading its ignore files from both sides
+    fn deeper(&self, dirs: &[&Path], file: &Path) -> SyncContext<'a> {
+        let mut ignores = self.ignores.clone();
+        for dir in dirs {
+            if let Some(ignore) = IgnoreFile::read(dir, file) {
+                ignores.push(Rc::new(ignore));
+            }
+        }
         SyncContext {
             current_depth: self.current_depth + 1,
             settings: self.settings,
+            ignores,
         }
     }
 
+    fn is_ignored(&self, src: &Path, dst: &Path, file: &Path) -> bool {
+        let is_dir = src.join(file).is_dir() || dst.join(file).is_dir();
+        ignore::is_ignored(&self.ignores, file, is_dir)
+    }
+
     fn too_deep(&self) -> bool {
         self.current_depth >= self.settings.depth && !self.settings.recursive
     }
 }
 
-fn checksum<A, B>(src: A, dst: B) -> Result<bool>
+fn checksum<A, B>(src: A, dst: B, links: bool) -> Result<bool>
 where
     A: AsRef<Path>,
     B: AsRef<Path>,
 {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    if links && (is_link(src) || is_link(dst)) {
+        return Ok(is_link(src) && is_link(dst) && fs::read_link(src)? == fs::read_link(dst)?);
+    }
     let mut fsrc = File::open(src)?;
     let mut fdst = File::open(dst)?;
     let mut bsrc = Vec::new();
@@ -146,6 +308,57 @@ where
     Ok(sumsrc == sumdst)
 }
 
+// Paths under root/file (relative to root) in sync order, parents first. Depth,
+// ignore files and links are handled like sync_diff does, excludes are not.
+struct Walk<'a> {
+    root: &'a Path,
+    // paths left to visit, with the context of their directory
+    stack: Vec<(PathBuf, SyncContext<'a>)>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(ctx: SyncContext<'a>, root: &'a Path, file: &Path) -> Walk<'a> {
+        Walk {
+            root,
+            stack: vec![(file.to_owned(), ctx)],
+        }
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Result<PathBuf>> {
+        loop {
+            let (file, ctx) = self.stack.pop()?;
+            if ctx.too_deep() || ctx.is_ignored(self.root, self.root, &file) {
+                continue;
+            }
+            let path = if file.parent().is_some() {
+                self.root.join(&file)
+            } else {
+                self.root.to_owned()
+            };
+            if let FileType::Dir = FileType::new(&path, ctx.settings.links) {
+                let entries = path
+                    .read_dir()
+                    .and_then(|e| e.collect::<io::Result<Vec<DirEntry>>>());
+                let entries = match entries {
+                    Ok(e) => e,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                let deeper = ctx.deeper(&[&path], &file);
+                // popped in directory order
+                for entry in entries.iter().rev() {
+                    self.stack
+                        .push((file.join(entry.file_name()), deeper.clone()));
+                }
+            }
+            return Some(Ok(file));
+        }
+    }
+}
+
 fn sync_diff_rec<A, B, C>(ctx: SyncContext, src_root: A, dst_root: B, file: C) -> Result<Vec<Diff>>
 where
     A: AsRef<Path>,
@@ -165,12 +378,16 @@ where
         src = src.join(file);
         dst = dst.join(file);
     }
-    let src_ty = FileType::new(&src);
-    let dst_ty = FileType::new(&dst);
+    if ctx.is_ignored(src_root, dst_root, file) {
+        return Ok(diffs);
+    }
+    let links = ctx.settings.links;
+    let src_ty = FileType::new(&src, links);
+    let dst_ty = FileType::new(&dst, links);
 
     match (&src_ty, &dst_ty) {
         (FileType::File, FileType::File) => {
-            if !checksum(&src, &dst)? {
+            if !checksum(&src, &dst, links)? {
                 diffs.push(Diff::new(file, DiffKind::Modified));
             }
         }
@@ -184,32 +401,30 @@ where
             if dst_ty.exists() {
                 diffs.push(Diff::new(file, DiffKind::Deleted));
             }
-            diffs.push(Diff::new(file, DiffKind::Added));
-            for entry in src.read_dir()? {
-                let entry: DirEntry = entry?;
-                let entry_diffs = sync_diff_rec(
-                    ctx.deeper(),
-                    &src_root,
-                    &dst_root,
-                    file.join(entry.file_name()),
-                )?;
-                diffs.extend(entry_diffs.into_iter());
+            // nothing of it on destination side, everything walked is added
+            for path in Walk::new(ctx, src_root, file) {
+                diffs.push(Diff::new(path?, DiffKind::Added));
             }
         }
         (FileType::File, FileType::Dir) | (FileType::None, FileType::Dir) => {
+            // directory can only be removed if all its content is
+            let mut empty = true;
+            let deeper = ctx.deeper(&[&src, &dst], file);
             for entry in dst.read_dir()? {
                 let entry: DirEntry = entry?;
-                let entry_diffs = sync_diff_rec(
-                    ctx.deeper(),
-                    &src_root,
-                    &dst_root,
-                    file.join(entry.file_name()),
-                )?;
-                diffs.extend(entry_diffs.into_iter());
+                let path = file.join(entry.file_name());
+                let entry_diffs =
+                    sync_diff_rec(deeper.clone(), src_root, dst_root, path.as_path())?;
+                if !entry_diffs.contains(&Diff::new(path, DiffKind::Deleted)) {
+                    empty = false;
+                }
+                diffs.extend(entry_diffs);
             }
-            diffs.push(Diff::new(file, DiffKind::Deleted));
-            if src_ty.exists() {
-                diffs.push(Diff::new(file, DiffKind::Added));
+            if empty {
+                diffs.push(Diff::new(file, DiffKind::Deleted));
+                if src_ty.exists() {
+                    diffs.push(Diff::new(file, DiffKind::Added));
+                }
             }
         }
         (FileType::Dir, FileType::Dir) => {
@@ -220,9 +435,10 @@ where
             dst.read_dir()?
                 .map(|f| f.map(|f| hash_set.insert(file.join(f.file_name()))))
                 .collect::<std::io::Result<Vec<_>>>()?;
+            let deeper = ctx.deeper(&[&src, &dst], file);
             for file in hash_set {
-                let entry_diffs = sync_diff_rec(ctx.deeper(), &src_root, &dst_root, file)?;
-                diffs.extend(entry_diffs.into_iter());
+                let entry_diffs = sync_diff_rec(deeper.clone(), src_root, dst_root, file)?;
+                diffs.extend(entry_diffs);
             }
         }
         (FileType::None, FileType::None) => {}
@@ -240,62 +456,329 @@ where
 {
     let src: &Path = src.as_ref();
     let dst: &Path = dst.as_ref();
-    if !src.exists() {
-        return Err(io::Error::from(io::ErrorKind::NotFound).into());
+    // a link is there even if its target is not
+    let present = src.exists() || (settings.links && is_link(src));
+    if !present {
+        return Err(DotError::NotFound(src.display().to_string()).into());
     }
     let ctx = SyncContext {
         current_depth: 0,
         settings,
+        ignores: Vec::new(),
     };
-    let mut diffs = sync_diff_rec(ctx, src, dst, "")?;
-    for exclude in &settings.exclude {
-        diffs.retain(|p| !exclude.matches_path(&p.path));
+    let diffs = sync_diff_rec(ctx, src, dst, "")?;
+    Ok(settings.filter(diffs))
+}
+
+// Files under root selected by settings, root itself when it is a file
+pub fn select<A: AsRef<Path>>(root: A, settings: &SyncSettings) -> Result<Vec<PathBuf>> {
+    let root = root.as_ref();
+    if !root.exists() {
+        return Err(DotError::NotFound(root.display().to_string()).into());
+    }
+    let ctx = SyncContext {
+        current_depth: 0,
+        settings,
+        ignores: Vec::new(),
+    };
+    // everything sync would copy to a missing destination
+    let added = Walk::new(ctx, root, Path::new(""))
+        .map(|p| p.map(|p| Diff::new(p, DiffKind::Added)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(settings
+        .filter(added)
+        .into_iter()
+        .map(|d| {
+            if d.path.as_os_str().is_empty() {
+                root.to_owned()
+            } else {
+                root.join(d.path)
+            }
+        })
+        .filter(|p| !p.is_dir())
+        .collect())
+}
+
+// Split diffs, everything at or under one of paths is skipped.
+// Returns (kept, skipped).
+pub fn skip_paths(diffs: Vec<Diff>, paths: &[PathBuf]) -> (Vec<Diff>, Vec<Diff>) {
+    diffs
+        .into_iter()
+        .partition(|d| !paths.iter().any(|p| d.path.starts_with(p)))
+}
+
+// Split deletions from diffs, paths replaced by another file type are skipped too.
+// Returns (kept, skipped).
+pub fn skip_deletions(diffs: Vec<Diff>) -> (Vec<Diff>, Vec<Diff>) {
+    let deleted = diffs
+        .iter()
+        .filter(|d| d.kind == DiffKind::Deleted)
+        .map(|d| d.path.to_owned())
+        .collect::<Vec<_>>();
+    skip_paths(diffs, &deleted)
+}
+
+// Deletion guards checked by sync, shared by all entries of a run
+#[derive(Debug, Default)]
+pub struct Guard {
+    // paths never deleted, neither are their parents (e.g $HOME, locations bases)
+    protected: Vec<PathBuf>,
+    // maximum deletions in a run, no limit if none
+    max_deletions: Option<usize>,
+    // move deleted files to trash instead of unlinking them
+    trash: Option<Trash>,
+    deleted: usize,
+}
+
+// Number of deleted paths, each file of a deleted directory counts
+pub fn count_deletions(diffs: &[Diff]) -> usize {
+    diffs.iter().filter(|d| d.kind == DiffKind::Deleted).count()
+}
+
+impl Guard {
+    pub fn new(
+        protected: Vec<PathBuf>,
+        max_deletions: Option<usize>,
+        trash: Option<Trash>,
+    ) -> Guard {
+        Guard {
+            protected,
+            max_deletions,
+            trash,
+            deleted: 0,
+        }
+    }
+
+    // Check that count more deletions stay under the limit of the run
+    pub fn check_count(&self, count: usize) -> Result<()> {
+        match self.max_deletions {
+            Some(max) if self.deleted + count > max => {
+                Err(DotError::TooManyDeletions(self.deleted + count, max).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Check every deletion of diffs under root, nothing must be applied if one is refused
+    pub fn check<P: AsRef<Path>>(&self, root: P, diffs: &[Diff]) -> Result<()> {
+        let root = root.as_ref();
+        self.check_count(count_deletions(diffs))?;
+        let real_root = fs::canonicalize(root).unwrap_or_else(|_| root.to_owned());
+        for diff in diffs.iter().filter(|d| d.kind == DiffKind::Deleted) {
+            let path = diff.full_path(root);
+            let refuse = || -> Result<()> {
+                Err(DotError::UnsafeDeletion(path.display().to_string()).into())
+            };
+            // diff must stay inside root, even through symlinks
+            if diff
+                .path
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+            {
+                return refuse();
+            }
+            if let Some(parent) = path.parent().filter(|_| diff.path.parent().is_some()) {
+                if let Ok(real) = fs::canonicalize(parent) {
+                    if !real.starts_with(&real_root) {
+                        return refuse();
+                    }
+                }
+            }
+            if self.protected.iter().any(|p| p.starts_with(&path)) {
+                return refuse();
+            }
+        }
+        Ok(())
+    }
+
+    // Account for deletions applied by someone else (e.g privileged helper), deleted
+    // paths are copied to trash first, removing them is left to the caller
+    pub fn delegate<P: AsRef<Path>>(&mut self, root: P, diffs: &[Diff]) -> Result<()> {
+        let root = root.as_ref();
+        self.check(root, diffs)?;
+        let deleted = deleted_paths(diffs);
+        for diff in diffs.iter().filter(|d| d.kind == DiffKind::Deleted) {
+            if let Some(trash) = &self.trash {
+                if !in_deleted_dir(&deleted, &diff.path) {
+                    trash.put_copy(&diff.full_path(root))?;
+                }
+            }
+            self.deleted += 1;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        if let Some(trash) = &self.trash {
+            return trash.put(path);
+        }
+        if path.is_dir() {
+            fs::remove_dir(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let (from, to) = (entry.path(), dst.join(entry.file_name()));
+        let ty = entry.file_type()?;
+        if ty.is_symlink() {
+            symlink(fs::read_link(&from)?, &to)?;
+        } else if ty.is_dir() {
+            copy_dir(&from, &to)?;
+        } else {
+            fs::copy(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+// Move file or directory, it is copied when rename is not possible (e.g other file system)
+pub fn move_path(old: &Path, new: &Path) -> Result<()> {
+    if let Some(parent) = new.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(old, new).is_ok() {
+        return Ok(());
+    }
+    copy_path(old, new)?;
+    if old.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(old)?;
+    } else {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+// Copy file, symlink or directory with its content
+pub fn copy_path(src: &Path, dst: &Path) -> Result<()> {
+    let ty = src.symlink_metadata()?.file_type();
+    if ty.is_symlink() {
+        symlink(fs::read_link(src)?, dst)?;
+    } else if ty.is_dir() {
+        copy_dir(src, dst)?;
+    } else {
+        fs::copy(src, dst)?;
+    }
+    Ok(())
+}
+
+// Private scratch directory under system temp directory, removed on drop
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    // Directory is always a new one, an existing path is never reused
+    pub fn new(prefix: &str) -> Result<TempDir> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let mut attempt = 0;
+        loop {
+            let name = format!("dotto-{}-{}-{}-{}", prefix, process::id(), nanos, attempt);
+            let path = env::temp_dir().join(name);
+            match fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
     }
-    Ok(diffs)
 }
 
 // TODO: add option for progress
 // TODO: support symlink
 
-// One way sync from src to dst
-pub fn sync<A, B>(src: A, dst: B, diffs: &[Diff]) -> Result<()>
+fn deleted_paths(diffs: &[Diff]) -> Vec<&Path> {
+    diffs
+        .iter()
+        .filter(|d| d.kind == DiffKind::Deleted)
+        .map(|d| d.path.as_path())
+        .collect()
+}
+
+// Check if path is inside another deleted path
+fn in_deleted_dir(deleted: &[&Path], path: &Path) -> bool {
+    deleted.iter().any(|p| *p != path && path.starts_with(p))
+}
+
+// One way sync from src to dst, deletions are checked by guard first
+pub fn sync<A, B>(src: A, dst: B, diffs: &[Diff], guard: &mut Guard) -> Result<()>
+where
+    A: AsRef<Path>,
+    B: AsRef<Path>,
+{
+    apply(src.as_ref(), dst.as_ref(), diffs, guard, false)
+}
+
+// Same as sync, for diffs computed with symlinks synced as links
+pub fn sync_links<A, B>(src: A, dst: B, diffs: &[Diff], guard: &mut Guard) -> Result<()>
 where
     A: AsRef<Path>,
     B: AsRef<Path>,
 {
-    let src = src.as_ref();
-    let dst = dst.as_ref();
-    if src.is_dir() {
+    apply(src.as_ref(), dst.as_ref(), diffs, guard, true)
+}
+
+// Copy file content, or the link itself when synced as link
+fn copy_file(src: &Path, dst: &Path, links: bool) -> Result<()> {
+    if !links || !is_link(src) {
+        fs::copy(src, dst)?;
+        return Ok(());
+    }
+    if dst.symlink_metadata().is_ok() {
+        fs::remove_file(dst)?;
+    }
+    symlink(fs::read_link(src)?, dst)?;
+    Ok(())
+}
+
+fn apply(src: &Path, dst: &Path, diffs: &[Diff], guard: &mut Guard, links: bool) -> Result<()> {
+    guard.check(dst, diffs)?;
+    if src.is_dir() && !(links && is_link(src)) {
         fs::create_dir_all(dst)?;
     } else if let Some(parent) = dst.parent() {
         fs::create_dir_all(parent)?;
     }
+    let deleted = deleted_paths(diffs);
     for diff in diffs {
-        let mut src_path = src.to_owned();
-        let mut dst_path = dst.to_owned();
-        if diff.path().parent().is_some() {
-            src_path = src_path.join(diff.path());
-            dst_path = dst_path.join(diff.path());
-        }
+        let src_path = diff.full_path(src);
+        let dst_path = diff.full_path(dst);
         match diff.kind() {
-            DiffKind::Modified => {
-                fs::copy(src_path, dst_path)?;
-            }
+            DiffKind::Modified => copy_file(&src_path, &dst_path, links)?,
             DiffKind::Added => {
-                if src_path.is_dir() {
+                if src_path.is_dir() && !(links && is_link(&src_path)) {
                     fs::create_dir_all(dst_path)?;
                 } else {
-                    fs::copy(src_path, dst_path)?;
+                    copy_file(&src_path, &dst_path, links)?;
                 }
             }
             DiffKind::Deleted => {
-                if dst_path.is_dir() {
-                    fs::remove_dir(dst_path)?;
-                } else {
-                    fs::remove_file(dst_path)?;
+                // trashed directories are moved with their content
+                let moved = guard.trash.is_some() && in_deleted_dir(&deleted, &diff.path);
+                if !moved {
+                    guard.remove(&dst_path)?;
                 }
+                guard.deleted += 1;
             }
         }
     }
-    return Ok(());
+    Ok(())
 }
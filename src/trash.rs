@@ -0,0 +1,88 @@
+//! Freedesktop.org trash, deleted files can be restored from file managers
+
+use crate::error::{DotError, Result};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct Trash {
+    // trash directory, containing files and info directories
+    dir: PathBuf,
+}
+
+// Percent encode path as required by trash info
+fn encode(path: &Path) -> String {
+    let mut out = String::new();
+    for b in path.as_os_str().as_bytes() {
+        match *b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(*b as char)
+            }
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+impl Trash {
+    // Home trash, in $XDG_DATA_HOME/Trash
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Trash {
+        Trash { dir: dir.into() }
+    }
+
+    // Move path (file or directory) to trash
+    pub fn put(&self, path: &Path) -> Result<()> {
+        let dst = self.reserve(path)?;
+        crate::sync::move_path(path, &dst)
+    }
+
+    // Copy path to trash, removing it is left to the caller (e.g privileged helper)
+    pub fn put_copy(&self, path: &Path) -> Result<()> {
+        let dst = self.reserve(path)?;
+        crate::sync::copy_path(path, &dst)
+    }
+
+    // Write trash info for path, returns where path goes in trash
+    fn reserve(&self, path: &Path) -> Result<PathBuf> {
+        let files = self.dir.join("files");
+        let info = self.dir.join("info");
+        fs::create_dir_all(&files)?;
+        fs::create_dir_all(&info)?;
+        let name = path
+            .file_name()
+            .ok_or_else(|| DotError::NotFound(path.display().to_string()))?;
+        // info file is created first, it reserves the name
+        let mut n = 1;
+        let (name, mut file) = loop {
+            let mut candidate = OsString::from(name);
+            if n > 1 {
+                candidate.push(format!(".{}", n));
+            }
+            let mut info_name = candidate.clone();
+            info_name.push(".trashinfo");
+            let res = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(info.join(&info_name));
+            match res {
+                Ok(f) if !files.join(&candidate).exists() => break (candidate, f),
+                Ok(_) => {
+                    let _ = fs::remove_file(info.join(&info_name));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+            n += 1;
+        };
+        write!(
+            file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode(path),
+            crate::util::now()
+        )?;
+        Ok(files.join(name))
+    }
+}
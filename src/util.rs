@@ -0,0 +1,19 @@
+//! Small helpers shared by modules
+
+// Local time formatted as YYYY-MM-DDThh:mm:ss
+pub fn now() -> String {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let t = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&t, &mut tm);
+    }
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
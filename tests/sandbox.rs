@@ -0,0 +1,429 @@
+//! Runs dotto against throwaway `--home` and `--root` trees and checks the resulting files
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+
+const CONFIG: &str = "\
+files:
+  - path: .vimrc
+    location: home
+  - path: nvim
+    location: config
+    recursive: true
+";
+
+struct Sandbox {
+    dir: PathBuf,
+}
+
+impl Sandbox {
+    // Home with a dot directory holding CONFIG, nothing installed yet
+    fn new(name: &str) -> Sandbox {
+        let dir = env::temp_dir().join(format!("dotto-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let sandbox = Sandbox { dir };
+        fs::create_dir_all(sandbox.root()).unwrap();
+        sandbox.write(sandbox.dot().join("config.yml"), CONFIG);
+        sandbox
+    }
+
+    fn home(&self) -> PathBuf {
+        self.dir.join("home")
+    }
+
+    fn dot(&self) -> PathBuf {
+        self.home().join(".dot")
+    }
+
+    fn root(&self) -> PathBuf {
+        self.dir.join("root")
+    }
+
+    // Home as seen under --root
+    fn rooted_home(&self) -> PathBuf {
+        self.root()
+            .join(self.home().strip_prefix("/").unwrap_or(&self.home()))
+    }
+
+    fn write<P: AsRef<Path>>(&self, path: P, content: &str) {
+        let path = path.as_ref();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read<P: AsRef<Path>>(&self, path: P) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+
+    // Run dotto with sandbox home, environment cannot leak real locations in
+    fn dotto(&self, args: &[&str]) -> Output {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_dotto"));
+        cmd.env("HOME", self.home()).env_remove("DOT_PATH");
+        identity(&mut cmd);
+        for var in &[
+            "XDG_CONFIG_HOME",
+            "XDG_DATA_HOME",
+            "XDG_STATE_HOME",
+            "XDG_CACHE_HOME",
+            "XDG_BIN_HOME",
+        ] {
+            cmd.env_remove(var);
+        }
+        let out = cmd
+            .arg("--home")
+            .arg(self.home())
+            .args(args)
+            .output()
+            .unwrap();
+        print!("{}", String::from_utf8_lossy(&out.stdout));
+        eprint!("{}", String::from_utf8_lossy(&out.stderr));
+        out
+    }
+}
+
+// Commits must not depend on the user git config
+fn identity(cmd: &mut Command) {
+    for var in &["GIT_AUTHOR", "GIT_COMMITTER"] {
+        cmd.env(format!("{}_NAME", var), "dotto")
+            .env(format!("{}_EMAIL", var), "dotto@localhost");
+    }
+    cmd.env("GIT_CONFIG_NOSYSTEM", "1");
+}
+
+// Run git in dir, panics if it fails
+fn git<P: AsRef<Path>>(dir: P, args: &[&str]) -> String {
+    let mut cmd = Command::new("git");
+    identity(&mut cmd);
+    let out = cmd.current_dir(dir).args(args).output().unwrap();
+    assert!(out.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn install_into_root() {
+    let sandbox = Sandbox::new("install");
+    sandbox.write(sandbox.dot().join(".vimrc"), "set nu\n");
+    sandbox.write(sandbox.dot().join("nvim/init.vim"), "set rnu\n");
+    let root = sandbox.root();
+    let out = sandbox.dotto(&["--root", root.to_str().unwrap(), "install", "-y"]);
+    assert_eq!(out.status.code(), Some(0));
+
+    let home = sandbox.rooted_home();
+    assert_eq!(
+        sandbox.read(home.join(".vimrc")).as_deref(),
+        Some("set nu\n")
+    );
+    assert_eq!(
+        sandbox.read(home.join(".config/nvim/init.vim")).as_deref(),
+        Some("set rnu\n")
+    );
+    // real home is left alone
+    assert!(!sandbox.home().join(".vimrc").exists());
+    assert!(!sandbox.home().join(".config").exists());
+
+    let out = sandbox.dotto(&["--root", root.to_str().unwrap(), "install", "-y"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn update_mirrors_system() {
+    let sandbox = Sandbox::new("update");
+    let (home, dot) = (sandbox.home(), sandbox.dot());
+    sandbox.write(dot.join(".vimrc"), "set nu\n");
+    sandbox.write(dot.join("nvim/init.vim"), "set rnu\n");
+    sandbox.write(dot.join("nvim/old.vim"), "\n");
+    sandbox.write(home.join(".vimrc"), "set nonu\n");
+    sandbox.write(home.join(".config/nvim/init.vim"), "set rnu\n");
+    sandbox.write(home.join(".config/nvim/new.vim"), "set list\n");
+    let out = sandbox.dotto(&["update", "-y"]);
+    assert_eq!(out.status.code(), Some(0));
+
+    assert_eq!(
+        sandbox.read(dot.join(".vimrc")).as_deref(),
+        Some("set nonu\n")
+    );
+    assert_eq!(
+        sandbox.read(dot.join("nvim/new.vim")).as_deref(),
+        Some("set list\n")
+    );
+    assert!(!dot.join("nvim/old.vim").exists());
+
+    let out = sandbox.dotto(&["update", "-y"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn rm_stops_managing() {
+    let sandbox = Sandbox::new("rm");
+    let (home, dot) = (sandbox.home(), sandbox.dot());
+    sandbox.write(dot.join(".vimrc"), "set nu\n");
+    sandbox.write(dot.join("nvim/init.vim"), "set rnu\n");
+    sandbox.write(dot.join("nvim/secret.vim"), "\n");
+    sandbox.write(home.join(".vimrc"), "set nu\n");
+    sandbox.write(home.join(".config/nvim/secret.vim"), "\n");
+    let vimrc = home.join(".vimrc");
+    let secret = home.join(".config/nvim/secret.vim");
+    let out = sandbox.dotto(&[
+        "rm",
+        "--delete",
+        "--no-commit",
+        vimrc.to_str().unwrap(),
+        secret.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(0));
+
+    let config = sandbox.read(dot.join("config.yml")).unwrap();
+    assert!(!config.contains(".vimrc"));
+    assert!(config.contains("- secret.vim"));
+    assert!(!dot.join(".vimrc").exists());
+    assert!(!dot.join("nvim/secret.vim").exists());
+    assert!(dot.join("nvim/init.vim").exists());
+    // system files are kept
+    assert!(vimrc.exists());
+    assert!(secret.exists());
+}
+
+#[test]
+fn locations_in_dot_directory() {
+    let sandbox = Sandbox::new("locations");
+    let (home, dot) = (sandbox.home(), sandbox.dot());
+    sandbox.write(
+        dot.join("config.yml"),
+        "files:\n  - path: app\n    location: dta\n",
+    );
+    let out = sandbox.dotto(&["install", "-y"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("dta"));
+
+    // newer locations are kept apart under @<name>
+    sandbox.write(
+        dot.join("config.yml"),
+        "files:\n  - path: app\n    location: data\n    recursive: true\n",
+    );
+    sandbox.write(dot.join("@data/app/db"), "1\n");
+    let out = sandbox.dotto(&["install", "-y"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(
+        sandbox.read(home.join(".local/share/app/db")).as_deref(),
+        Some("1\n")
+    );
+}
+
+#[test]
+fn remote_sync() {
+    let remote = env::temp_dir().join(format!("dotto-test-remote-{}.git", process::id()));
+    let _ = fs::remove_dir_all(&remote);
+    git(
+        env::temp_dir(),
+        &[
+            "init",
+            "-q",
+            "--bare",
+            "-b",
+            "main",
+            remote.to_str().unwrap(),
+        ],
+    );
+    let config = format!(
+        "git:\n  path: .dot/.git\n  location: home\n  remote: {}\n  push: true\n  pull: true\nfiles:\n  - path: .vimrc\n    location: home\n",
+        remote.display()
+    );
+
+    // first machine pushes its update
+    let a = Sandbox::new("remote-a");
+    git(a.dot(), &["init", "-q", "-b", "main"]);
+    a.write(a.dot().join("config.yml"), &config);
+    a.write(a.home().join(".vimrc"), "set nu\n");
+    assert_eq!(a.dotto(&["update", "-y"]).status.code(), Some(0));
+    assert!(git(&remote, &["log", "--oneline"]).contains("update"));
+
+    // second machine installs from a clone
+    let b = Sandbox::new("remote-b");
+    fs::remove_dir_all(b.dot()).unwrap();
+    git(b.home(), &["clone", "-q", remote.to_str().unwrap(), ".dot"]);
+    assert_eq!(b.dotto(&["install", "-y"]).status.code(), Some(0));
+    assert_eq!(b.read(b.home().join(".vimrc")).as_deref(), Some("set nu\n"));
+
+    // entries pulled before install are installed in the same run
+    a.write(a.home().join(".bashrc"), "set -o vi\n");
+    let bashrc = a.home().join(".bashrc");
+    assert_eq!(
+        a.dotto(&["add", bashrc.to_str().unwrap()]).status.code(),
+        Some(0)
+    );
+    assert_eq!(a.dotto(&["update", "-y"]).status.code(), Some(0));
+    assert_eq!(b.dotto(&["install", "-y"]).status.code(), Some(0));
+    assert_eq!(
+        b.read(b.home().join(".bashrc")).as_deref(),
+        Some("set -o vi\n")
+    );
+
+    // diverged branches are not merged
+    a.write(a.home().join(".vimrc"), "set rnu\n");
+    assert_eq!(a.dotto(&["update", "-y"]).status.code(), Some(0));
+    b.write(b.dot().join(".vimrc"), "set list\n");
+    git(b.dot(), &["commit", "-q", "-am", "local"]);
+    let out = b.dotto(&["install", "-y"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("diverged (1 local, 1 remote commit(s))"));
+    assert!(stdout.contains(&format!("dotto git pull {}", remote.display())));
+    assert_eq!(b.read(b.home().join(".vimrc")).as_deref(), Some("set nu\n"));
+    let _ = fs::remove_dir_all(&remote);
+}
+
+#[test]
+fn layered_config() {
+    let sandbox = Sandbox::new("layers");
+    let (home, dot) = (sandbox.home(), sandbox.dot());
+    git(&dot, &["init", "-q"]);
+    sandbox.write(
+        dot.join("config.yml"),
+        "git:\n  path: .dot/.git\n  location: home\nfiles:\n  - path: .vimrc\n    location: home\n",
+    );
+    sandbox.write(
+        dot.join("config.d/shell.yml"),
+        "files:\n  - path: .bashrc\n    location: home\n",
+    );
+    sandbox.write(
+        dot.join("config.local.yml"),
+        "files:\n  - path: .work\n    location: home\n",
+    );
+    sandbox.write(home.join(".vimrc"), "set nu\n");
+    sandbox.write(home.join(".bashrc"), "set -o vi\n");
+    sandbox.write(home.join(".work"), "secret\n");
+    assert_eq!(sandbox.dotto(&["update", "-y"]).status.code(), Some(0));
+
+    // machine local config stays out of the repository, entries are committed
+    let tracked = git(&dot, &["ls-files"]);
+    assert!(tracked.contains("config.yml"));
+    assert!(tracked.contains("config.d/shell.yml"));
+    assert!(tracked.contains(".bashrc"));
+    assert!(!tracked.contains("config.local.yml"));
+    assert_eq!(sandbox.read(dot.join(".work")).as_deref(), Some("secret\n"));
+
+    // moved entries are kept in the layer defining them
+    let (old, new) = (home.join(".work"), home.join(".job"));
+    let out = sandbox.dotto(&["mv", old.to_str().unwrap(), new.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    let local = sandbox.read(dot.join("config.local.yml")).unwrap();
+    assert!(local.contains(".job") && !local.contains(".work"));
+    let shared = sandbox.read(dot.join("config.yml")).unwrap();
+    assert!(!shared.contains(".job"));
+    assert!(!git(&dot, &["ls-files"]).contains("config.local.yml"));
+}
+
+#[test]
+fn ignore_files_and_includes() {
+    let sandbox = Sandbox::new("ignore");
+    let (home, dot) = (sandbox.home(), sandbox.dot());
+    sandbox.write(
+        dot.join("config.yml"),
+        "files:\n  - path: nvim\n    location: config\n    recursive: true\n  - path: Code\n    location: config\n    recursive: true\n    include:\n      - '*.json'\n      - '!User/state.json'\n",
+    );
+    let nvim = home.join(".config/nvim");
+    sandbox.write(nvim.join(".dottoignore"), "/build\n*.log\n!keep.log\n");
+    sandbox.write(nvim.join("init.vim"), "\n");
+    sandbox.write(nvim.join("build/out"), "\n");
+    sandbox.write(nvim.join("lua/build"), "\n");
+    sandbox.write(nvim.join("lua/a.log"), "\n");
+    sandbox.write(nvim.join("keep.log"), "\n");
+    let code = home.join(".config/Code");
+    sandbox.write(code.join("User/settings.json"), "{}\n");
+    sandbox.write(code.join("User/state.json"), "{}\n");
+    sandbox.write(code.join("Cache/blob"), "\n");
+    assert_eq!(sandbox.dotto(&["update", "-y"]).status.code(), Some(0));
+
+    assert!(dot.join("nvim/.dottoignore").exists());
+    assert!(dot.join("nvim/init.vim").exists());
+    assert!(dot.join("nvim/keep.log").exists());
+    assert!(dot.join("nvim/lua/build").exists());
+    assert!(!dot.join("nvim/build").exists());
+    assert!(!dot.join("nvim/lua/a.log").exists());
+    assert!(dot.join("Code/User/settings.json").exists());
+    assert!(!dot.join("Code/User/state.json").exists());
+    assert!(!dot.join("Code/Cache").exists());
+
+    // ignored system files are not deleted by install
+    assert_eq!(sandbox.dotto(&["install", "-y"]).status.code(), Some(2));
+    assert!(nvim.join("build/out").exists());
+    assert!(code.join("User/state.json").exists());
+}
+
+#[test]
+fn deletion_guard() {
+    let sandbox = Sandbox::new("guard");
+    let (home, dot) = (sandbox.home(), sandbox.dot());
+    sandbox.write(dot.join("nvim/init.vim"), "\n");
+    let nvim = home.join(".config/nvim");
+    for name in &["init.vim", "a.vim", "b.vim", "c.vim"] {
+        sandbox.write(nvim.join(name), "\n");
+    }
+
+    // limit is opt-in, install refuses to go over it
+    let config = format!("max_deletions: 2\n{}", CONFIG);
+    sandbox.write(dot.join("config.yml"), &config);
+    let out = sandbox.dotto(&["install", "-y"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(nvim.join("a.vim").exists());
+
+    // deleted files are moved to trash
+    let config = format!("trash: true\n{}", CONFIG);
+    sandbox.write(dot.join("config.yml"), &config);
+    assert_eq!(sandbox.dotto(&["install", "-y"]).status.code(), Some(0));
+    assert!(!nvim.join("a.vim").exists());
+    assert!(nvim.join("init.vim").exists());
+    let trash = home.join(".local/share/Trash");
+    for name in &["a.vim", "b.vim", "c.vim"] {
+        assert!(trash.join("files").join(name).exists());
+        let info = sandbox.read(trash.join("info").join(format!("{}.trashinfo", name)));
+        assert!(info.unwrap().contains("[Trash Info]"));
+    }
+}
+
+#[test]
+fn restore_revision() {
+    let sandbox = Sandbox::new("restore");
+    let (home, dot) = (sandbox.home(), sandbox.dot());
+    git(&dot, &["init", "-q"]);
+    let config = format!("git:\n  path: .dot/.git\n  location: home\n{}", CONFIG);
+    sandbox.write(dot.join("config.yml"), &config);
+    let vimrc = home.join(".vimrc");
+    sandbox.write(&vimrc, "set nu\n");
+    sandbox.write(home.join(".config/nvim/init.vim"), "set rnu\n");
+    assert_eq!(sandbox.dotto(&["update", "-y"]).status.code(), Some(0));
+    sandbox.write(&vimrc, "set nonu\n");
+    fs::remove_file(home.join(".config/nvim/init.vim")).unwrap();
+    assert_eq!(sandbox.dotto(&["update", "-y"]).status.code(), Some(0));
+
+    let out = sandbox.dotto(&["log", vimrc.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let commits = stdout.lines().filter(|l| l.starts_with("commit "));
+    assert_eq!(commits.count(), 2);
+
+    let out = sandbox.dotto(&["restore", "--rev", "HEAD~1", vimrc.to_str().unwrap(), "-y"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(sandbox.read(&vimrc).as_deref(), Some("set nu\n"));
+    // directories are restored with their files
+    let nvim = home.join(".config/nvim");
+    let out = sandbox.dotto(&["restore", "--rev", "HEAD~1", nvim.to_str().unwrap(), "-y"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(
+        sandbox.read(nvim.join("init.vim")).as_deref(),
+        Some("set rnu\n")
+    );
+    // dot directory is left at its last revision
+    assert_eq!(
+        sandbox.read(dot.join(".vimrc")).as_deref(),
+        Some("set nonu\n")
+    );
+}